@@ -1,16 +1,57 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 
 use eframe::{
     egui_glow::{self, glow, ShaderVersion},
     glow::{Buffer, HasContext, VertexArray},
 };
 use egui::{mutex::Mutex, Checkbox, RichText, Slider};
-use glam::{vec3, Mat4, Quat, Vec3};
+use glam::{vec3, Mat4, Quat, Vec2, Vec3, Vec4};
 use poll_promise::Promise;
 use serde::{Deserialize, Serialize};
 use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
 
-#[derive(Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Default, IntoStaticStr, EnumIter)]
+mod camera;
+mod capping;
+mod clipping;
+mod gizmo;
+mod light;
+mod loading;
+mod material;
+mod obj;
+mod observer;
+mod outline;
+mod picking;
+mod polygon_clipping;
+mod rotation;
+mod scene;
+mod shadow;
+mod transform;
+mod validate;
+mod viewport;
+
+use camera::{
+    CameraBookmark, CameraMode, CameraPose, Flight, Orbit, Projection, ProjectionKind, MAX_PITCH,
+    SPRINT_FACTOR,
+};
+use capping::{CapRenderer, Capping};
+use clipping::{ClippingMode, ClippingPlane, ObjectClipping};
+use gizmo::Gizmo;
+use light::{Light, LightBlock, LightKind, LIGHTS_BINDING, MAX_LIGHTS};
+use loading::{LoadResult, ModelLoadError};
+use material::Material;
+use outline::Outline;
+use rotation::{AxisAngle, RotationMode};
+use shadow::ShadowMaps;
+use transform::Shear;
+use validate::{RepairOptions, ValidationReport};
+use viewport::{Layout, Pane};
+
+#[derive(
+    Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize, Default, IntoStaticStr, EnumIter,
+)]
 enum RenderingMode {
     Flat = 0,
     Gouraud,
@@ -24,12 +65,40 @@ enum RenderingMode {
 struct CGObject {
     name: String,
     translation: Vec3,
+    /// Euler angles in degrees, for the Euler rotation modes.
     rotation: Vec3,
+    #[serde(default)]
+    rotation_mode: RotationMode,
+    #[serde(default)]
+    axis_angle: AxisAngle,
+    #[serde(default)]
+    quaternion: Quat,
+    /// Angular velocity of the animation: spins around this axis (in the
+    /// parent's space) at its length in degrees per second.
     rotating: Vec3,
     scale: Vec3,
-    shear: Vec3,
+    /// The point in model space rotation, scale and shear happen around.
+    #[serde(default)]
+    pivot: Vec3,
+    #[serde(rename = "shear_factors", default)]
+    shear: Shear,
+    /// The three angles shear used to be stored as, only read from old state.
+    #[serde(
+        rename = "shear",
+        default,
+        skip_serializing,
+        deserialize_with = "deserialize_some"
+    )]
+    shear_angles: Option<Vec3>,
     rendering_mode: RenderingMode,
+    #[serde(default)]
+    material: Material,
     model_id: Option<(usize, usize)>,
+    /// Index of the object this one moves along with.
+    #[serde(default)]
+    parent: Option<usize>,
+    #[serde(default)]
+    clipping: ObjectClipping,
 }
 
 impl Default for CGObject {
@@ -38,60 +107,48 @@ impl Default for CGObject {
             name: "Object".into(),
             translation: Default::default(),
             rotation: Default::default(),
+            rotation_mode: Default::default(),
+            axis_angle: Default::default(),
+            quaternion: Quat::IDENTITY,
             rotating: Default::default(),
             scale: vec3(1., 1., 1.),
-            shear: vec3(90., 90., 90.),
+            pivot: Vec3::ZERO,
+            shear: Default::default(),
+            shear_angles: None,
             rendering_mode: Default::default(),
+            material: Default::default(),
             model_id: Default::default(),
+            parent: None,
+            clipping: Default::default(),
         }
     }
 }
 
 impl CGObject {
     fn tick_animation(&mut self, dt: f32) {
-        for i in 0..3 {
-            self.rotation[i] += self.rotating[i] * dt;
-            if self.rotation[i] > 180. {
-                self.rotation[i] -= 360.;
-            };
-            if self.rotation[i] < -180. {
-                self.rotation[i] += 360.;
-            };
+        let speed = self.rotating.length();
+        if speed > 0. {
+            self.rotate(self.rotating / speed, speed * dt);
         }
     }
 
+    /// Local to parent space, see [`scene::world_matrices`] for the whole way
+    /// to world space.
     fn mv_matrix(&self) -> Mat4 {
-        Mat4::from_scale_rotation_translation(
-            self.scale,
-            Quat::from_rotation_x(self.rotation.x.to_radians())
-                * Quat::from_rotation_y(self.rotation.y.to_radians())
-                * Quat::from_rotation_z(self.rotation.z.to_radians()),
-            self.translation,
-        ) * Mat4::from_cols_array(&[
-            1.,
-            0.,
-            self.shear.z.to_radians().tan().recip(),
-            0.,
-            self.shear.x.to_radians().tan().recip(),
-            1.,
-            0.,
-            0.,
-            0.,
-            self.shear.y.to_radians().tan().recip(),
-            1.,
-            0.,
-            0.,
-            0.,
-            0.,
-            1.,
-        ])
+        Mat4::from_translation(self.translation + self.pivot)
+            * self.linear_matrix()
+            * Mat4::from_translation(-self.pivot)
     }
 
-    fn to_rendered(&self) -> RenderedObject {
+    /// `clipping` are the scene's plane equations.
+    fn to_rendered(&self, world: Mat4, clipping: &[Vec4]) -> RenderedObject {
         RenderedObject {
-            mv_mat: self.mv_matrix(),
+            mv_mat: world,
+            clipping: self.clipping.equations(clipping, world),
             mode: self.rendering_mode as i32,
+            material: self.material,
             model_id: self.model_id.map(|(_self_id, gl_id)| gl_id),
+            selected: false,
         }
     }
 }
@@ -100,8 +157,8 @@ impl CGObject {
 enum ModelState {
     #[default]
     Ready,
-    Loading(Promise<Option<ICGJson>>),
-    Failed,
+    Loading(Promise<LoadResult>),
+    Failed(ModelLoadError),
     Loaded(usize),
 }
 
@@ -111,52 +168,119 @@ struct CGModel {
     source: String,
     #[serde(skip)]
     state: ModelState,
+    /// Problems found (and possibly repaired) when the model was loaded.
+    #[serde(skip)]
+    report: Option<ValidationReport>,
 }
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(Deserialize, Serialize)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct DemoApp {
     ambient: [f32; 3],
-    ambient_ka: f32,
     rotation_enabled: bool,
     selected_object: Option<usize>,
+    /// Everything selected, including `selected_object`.
+    selection: BTreeSet<usize>,
+    gizmo: Gizmo,
     objects: Vec<CGObject>,
     dummy_object: CGObject,
+    lights: Vec<Light>,
     show_lights: bool,
     light_depth: bool,
-    clipping_pos: Vec3,
-    clipping_vec: Vec3,
+    clipping_planes: Vec<ClippingPlane>,
+    show_clipping_planes: bool,
+    clipping_mode: ClippingMode,
+    highlight_clipped_edges: bool,
+    capping: Capping,
+    /// The single clipping plane there used to be, only read from old state.
+    #[serde(skip_serializing, deserialize_with = "deserialize_some")]
+    clipping_pos: Option<Vec3>,
+    #[serde(skip_serializing, deserialize_with = "deserialize_some")]
+    clipping_vec: Option<Vec3>,
     #[serde(skip)]
     models: Arc<Mutex<BTreeMap<usize, CGModel>>>,
     model_source: String,
+    /// Base that relative model sources are resolved against, a URL or (on native) a directory.
+    #[serde(default = "default_asset_root")]
+    asset_root: String,
+    repair_options: RepairOptions,
     camera_pos: Vec3,
     camera_phi: f32,
     camera_up: f32,
+    camera_mode: CameraMode,
+    /// Units per second the keyboard moves the camera.
+    camera_speed: f32,
+    /// What the orbit and arcball modes turn around.
+    camera_target: Vec3,
     fovy: f32,
+    projection: Projection,
+    bookmarks: Vec<CameraBookmark>,
+    bookmark_name: String,
+    #[serde(skip)]
+    flight: Option<Flight>,
+    /// Aspect ratio of the last pane drawn with the main camera.
+    #[serde(skip)]
+    main_aspect: f32,
+    layout: Layout,
+    panes: Vec<Pane>,
     #[serde(skip)]
     gl_stuff: Arc<Mutex<Option<GLStuff>>>,
 }
 
+/// Reads a plain value into an `Option`, for fields that only old state has
+/// and that were stored without one.
+fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+fn default_asset_root() -> String {
+    ".".into()
+}
+
 impl Default for DemoApp {
     fn default() -> Self {
         Self {
             ambient: Default::default(),
-            ambient_ka: Default::default(),
             rotation_enabled: Default::default(),
             selected_object: Default::default(),
+            selection: Default::default(),
+            gizmo: Default::default(),
             objects: Default::default(),
             dummy_object: Default::default(),
             models: Default::default(),
             model_source: "/model/".into(),
+            asset_root: default_asset_root(),
+            repair_options: Default::default(),
             camera_pos: vec3(0., 0., 25.),
             camera_phi: Default::default(),
             camera_up: Default::default(),
+            camera_mode: Default::default(),
+            camera_speed: 6.,
+            camera_target: Vec3::ZERO,
             fovy: 60f32,
+            projection: Default::default(),
+            bookmarks: Default::default(),
+            bookmark_name: "View".into(),
+            flight: None,
+            main_aspect: 1.,
+            layout: Default::default(),
+            panes: Pane::default_panes(),
             gl_stuff: Default::default(),
+            lights: Light::default_lights(),
             show_lights: true,
             light_depth: true,
-            clipping_pos: vec3(0., 0., -100.),
-            clipping_vec: vec3(0., 0., 1.),
+            clipping_planes: clipping::default_planes(),
+            show_clipping_planes: true,
+            clipping_mode: Default::default(),
+            highlight_clipped_edges: true,
+            capping: Default::default(),
+            clipping_pos: None,
+            clipping_vec: None,
         }
     }
 }
@@ -175,11 +299,30 @@ impl DemoApp {
             DemoApp::default()
         };
 
+        value.migrate();
+
         let gl = cc.gl.as_ref()?;
 
         value.gl_stuff = Arc::new(Mutex::new(Some(GLStuff::new(gl)?)));
         Some(value)
     }
+
+    /// Moves what only old state has into the fields that replaced it.
+    fn migrate(&mut self) {
+        for obj in self.objects.iter_mut().chain([&mut self.dummy_object]) {
+            if let Some(angles) = obj.shear_angles.take() {
+                obj.shear = Shear::from_angles(angles);
+            }
+        }
+
+        if let (Some(pos), Some(normal)) = (self.clipping_pos.take(), self.clipping_vec.take()) {
+            self.clipping_planes = vec![ClippingPlane {
+                pos,
+                normal,
+                ..Default::default()
+            }];
+        }
+    }
 }
 
 impl eframe::App for DemoApp {
@@ -190,28 +333,24 @@ impl eframe::App for DemoApp {
 
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        ctx.input(|i| {
-            if i.key_down(egui::Key::W) {
-                self.camera_pos.z -= 0.1;
-            }
-            if i.key_down(egui::Key::S) {
-                self.camera_pos.z += 0.1;
-            }
-            if i.key_down(egui::Key::A) {
-                self.camera_pos.x -= 0.1;
-            }
-            if i.key_down(egui::Key::D) {
-                self.camera_pos.x += 0.1;
-            }
-            if i.key_down(egui::Key::E) {
-                self.camera_pos.y += 0.1;
-            }
-            if i.key_down(egui::Key::Q) {
-                self.camera_pos.y -= 0.1;
-            }
-        });
+        scene::fix_parents(&mut self.objects);
+        self.fix_selection();
+        if !ctx.wants_keyboard_input() {
+            self.fly(ctx);
+            self.camera_hotkeys(ctx);
+            self.gizmo.hotkeys(ctx);
+        }
 
         let dt = ctx.input(|i| i.unstable_dt);
+        if let Some(flight) = &mut self.flight {
+            let (pose, arrived) = flight.step(ctx.input(|i| i.stable_dt.min(0.1)));
+            let target_distance = flight.target_distance;
+            if arrived {
+                self.flight = None;
+            }
+            self.set_camera_pose(pose, target_distance);
+            ctx.request_repaint();
+        }
         if self.rotation_enabled {
             for obj in &mut self.objects {
                 obj.tick_animation(dt);
@@ -248,30 +387,71 @@ impl eframe::App for DemoApp {
             ui.separator();
 
             ui.heading("Ambient Light");
-            ui.add(Slider::new(&mut self.ambient_ka, 0.0..=1.0).text("Ka"));
             ui.add(Slider::new(&mut self.ambient[0], 0.0..=1.0).text("Red"));
             ui.add(Slider::new(&mut self.ambient[1], 0.0..=1.0).text("Green"));
             ui.add(Slider::new(&mut self.ambient[2], 0.0..=1.0).text("Blue"));
+            ui.separator();
+
+            ui.heading("Lights");
             ui.checkbox(&mut self.show_lights, "Show lights?");
             ui.checkbox(&mut self.light_depth, "Light object with depth?");
+            self.light_settings(ui);
             ui.separator();
 
             ui.heading("Camera");
+            let camera_mode = self.camera_mode;
+            egui::ComboBox::new("camera_mode", "Mode")
+                .selected_text(Into::<&'static str>::into(self.camera_mode))
+                .show_ui(ui, |ui| {
+                    for mode in CameraMode::iter() {
+                        ui.selectable_value(
+                            &mut self.camera_mode,
+                            mode,
+                            Into::<&'static str>::into(mode),
+                        );
+                    }
+                });
+            if self.camera_mode != camera_mode && self.camera_mode != CameraMode::Free {
+                self.orbit().aim();
+            }
+            if self.camera_mode == CameraMode::Free {
+                ui.label("Right drag to look around.");
+            } else {
+                ui.label("Drag to turn, middle drag to pan, scroll to zoom.");
+                ui.label(format!("target: {:.2}", self.camera_target));
+            }
+            ui.add(
+                Slider::new(&mut self.camera_speed, 0.5..=50.)
+                    .logarithmic(true)
+                    .text("speed (hold Shift to sprint)"),
+            );
+            ui.label("Ctrl + drag away from the gizmo moves the selected object.");
             ui.label(format!("x: {}", self.camera_pos.x));
             ui.label(format!("y: {}", self.camera_pos.y));
             ui.label(format!("z: {}", self.camera_pos.z));
-            ui.add(Slider::new(&mut self.fovy, 0.0..=180.).text("fov"));
-            ui.add(Slider::new(&mut self.camera_up, -90.0..=90.).text("angle"));
+            self.projection.ui(ui, &mut self.fovy);
+            ui.add(Slider::new(&mut self.camera_up, -MAX_PITCH..=MAX_PITCH).text("angle"));
             ui.add(Slider::new(&mut self.camera_phi, -180.0..=180.).text("phi"));
+            if ui.button("Frame Selected Object (F)").clicked() {
+                self.frame_selected();
+            }
+            egui::CollapsingHeader::new("Bookmarks").show(ui, |ui| {
+                self.bookmark_settings(ui);
+            });
+            ui.separator();
+
+            ui.heading("Viewports");
+            self.viewport_settings(ui);
             ui.separator();
 
             ui.heading("Clipping");
-            ui.add(Slider::new(&mut self.clipping_pos[0], -100.0..=100.0).text("Pos X"));
-            ui.add(Slider::new(&mut self.clipping_pos[1], -100.0..=100.0).text("Pos Y"));
-            ui.add(Slider::new(&mut self.clipping_pos[2], -100.0..=100.0).text("Pos Z"));
-            ui.add(Slider::new(&mut self.clipping_vec[0], -1.0..=1.0).text("Vec X"));
-            ui.add(Slider::new(&mut self.clipping_vec[1], -1.0..=1.0).text("Vec Y"));
-            ui.add(Slider::new(&mut self.clipping_vec[2], -1.0..=1.0).text("Vec Z"));
+            self.clipping_settings(ui);
+            let has_stencil = self
+                .gl_stuff
+                .lock()
+                .as_ref()
+                .is_some_and(|stuff| stuff.has_stencil);
+            self.capping.ui(ui, has_stencil);
             ui.separator();
 
             ui.heading("Objects");
@@ -323,6 +503,41 @@ fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
 }
 
 impl DemoApp {
+    fn light_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    self.lights.len() < MAX_LIGHTS,
+                    egui::Button::new("New Light"),
+                )
+                .clicked()
+            {
+                self.lights.push(Light::default());
+            }
+            if ui.button("Reset Lights").clicked() {
+                self.lights = Light::default_lights();
+            }
+        });
+
+        let mut removed = None;
+        for (id, light) in self.lights.iter_mut().enumerate() {
+            egui::CollapsingHeader::new(format!("Light {id}"))
+                .id_salt(("light", id))
+                .show(ui, |ui| {
+                    light.ui(ui, id);
+                    if ui
+                        .button(RichText::new("Remove Light").color(egui::Color32::RED))
+                        .clicked()
+                    {
+                        removed = Some(id);
+                    }
+                });
+        }
+        if let Some(id) = removed {
+            self.lights.remove(id);
+        }
+    }
+
     fn object_settings(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             if ui.button("New Object").clicked() {
@@ -339,23 +554,17 @@ impl DemoApp {
                 .clicked()
             {
                 self.objects.clear();
-                self.selected_object = None;
+                self.select(None, false);
             }
         });
         ui.add_space(10.);
 
-        egui::ComboBox::new("obj", "Selected Object")
-            .selected_text(
-                self.selected_object
-                    .and_then(|obj_id| self.objects.get(obj_id))
-                    .map(|obj| obj.name.as_str())
-                    .unwrap_or("None"),
-            )
-            .show_ui(ui, |ui| {
-                for (id, obj) in self.objects.iter().enumerate() {
-                    ui.selectable_value(&mut self.selected_object, Some(id), &obj.name);
-                }
-            });
+        self.object_tree(ui);
+        ui.add_space(10.);
+
+        egui::CollapsingHeader::new("Gizmo").show(ui, |ui| {
+            self.gizmo.ui(ui);
+        });
 
         {
             let selected = self.selected_object.is_some();
@@ -439,70 +648,39 @@ impl DemoApp {
                 }
             });
             ui.collapsing("Rotation", |ui| {
-                ui.add(
-                    Slider::new(&mut selected_obj.rotation[0], -180.0..=180.0).text("Rotation.x"),
-                );
-                ui.add(
-                    Slider::new(&mut selected_obj.rotation[1], -180.0..=180.0).text("Rotation.y"),
-                );
-                ui.add(
-                    Slider::new(&mut selected_obj.rotation[2], -180.0..=180.0).text("Rotation.z"),
-                );
-                ui.horizontal(|ui| {
-                    if ui.button("X +90°").clicked() {
-                        selected_obj.rotation.x += 90.;
-                        if selected_obj.rotation.x > 180. {
-                            selected_obj.rotation.x -= 360.;
-                        }
-                    }
-                    if ui.button("Y +90°").clicked() {
-                        selected_obj.rotation.y += 90.;
-                        if selected_obj.rotation.y > 180. {
-                            selected_obj.rotation.y -= 360.;
-                        }
-                    }
-                    if ui.button("Z +90°").clicked() {
-                        selected_obj.rotation.z += 90.;
-                        if selected_obj.rotation.z > 180. {
-                            selected_obj.rotation.z -= 360.;
-                        }
-                    }
-                });
-                ui.horizontal(|ui| {
-                    if ui.button("X +180°").clicked() {
-                        selected_obj.rotation.x += 180.;
-                        if selected_obj.rotation.x > 180. {
-                            selected_obj.rotation.x -= 360.;
-                        }
-                    }
-                    if ui.button("Y +180°").clicked() {
-                        selected_obj.rotation.y += 180.;
-                        if selected_obj.rotation.y > 180. {
-                            selected_obj.rotation.y -= 360.;
-                        }
-                    }
-                    if ui.button("Z +180°").clicked() {
-                        selected_obj.rotation.z += 180.;
-                        if selected_obj.rotation.z > 180. {
-                            selected_obj.rotation.z -= 360.;
-                        }
-                    }
-                });
-
-                if ui.button("Reset Rotation").clicked() {
-                    selected_obj.rotation = vec3(0., 0., 0.);
-                }
+                selected_obj.rotation_ui(ui);
             });
             ui.collapsing("Shear", |ui| {
-                ui.add(Slider::new(&mut selected_obj.shear[0], 0.0..=180.0).text("Shear.x"));
-                ui.add(Slider::new(&mut selected_obj.shear[1], 0.0..=180.0).text("Shear.y"));
-                ui.add(Slider::new(&mut selected_obj.shear[2], 0.0..=180.0).text("Shear.z"));
+                selected_obj.shear.ui(ui);
 
                 if ui.button("Reset Shear").clicked() {
-                    selected_obj.shear = vec3(90., 90., 90.);
+                    selected_obj.shear = Default::default();
+                }
+            });
+            ui.collapsing("Pivot", |ui| {
+                let bounds = self.gl_stuff.lock().as_ref().map(|stuff| {
+                    stuff
+                        .model(selected_obj.model_id.map(|(_self_id, gl_id)| gl_id))
+                        .bounds
+                });
+                selected_obj.pivot_ui(ui, bounds);
+            });
+            ui.collapsing("Clipping", |ui| {
+                let bounds = self.gl_stuff.lock().as_ref().map(|stuff| {
+                    stuff
+                        .model(selected_obj.model_id.map(|(_self_id, gl_id)| gl_id))
+                        .bounds
+                });
+                selected_obj.clipping.ui(ui, bounds);
+            });
+            ui.collapsing("Material", |ui| {
+                selected_obj.material.ui(ui);
+                if ui.button("Reset Material").clicked() {
+                    selected_obj.material = Default::default();
                 }
             });
             ui.collapsing("Animation", |ui| {
+                ui.label("Spins around this axis at its length in degrees per second.");
                 ui.add(
                     Slider::new(&mut selected_obj.rotating[0], -360.0..=360.0).text("Rotating.x"),
                 );
@@ -518,28 +696,89 @@ impl DemoApp {
                 }
             });
         }
+        ui.collapsing("Transform Pipeline", |ui| {
+            self.pipeline_inspector(ui);
+        });
+    }
+
+    /// The objects as a tree, drag one onto another to attach it.
+    fn object_tree(&mut self, ui: &mut egui::Ui) {
+        let mut reparent = None;
+        for id in scene::children(&self.objects, None) {
+            self.object_tree_node(ui, id, &mut reparent);
+        }
+        let (_, dropped) = ui.dnd_drop_zone::<usize, _>(egui::Frame::group(ui.style()), |ui| {
+            ui.label("Drop here to detach from the parent");
+        });
+        if let Some(id) = dropped {
+            reparent = Some((*id, None));
+        }
+
+        if let Some((id, parent)) = reparent {
+            if !scene::reparent(&mut self.objects, id, parent) {
+                log::warn!("Cannot attach an object to its own descendant");
+            }
+        }
+    }
+
+    fn object_tree_node(
+        &mut self,
+        ui: &mut egui::Ui,
+        id: usize,
+        reparent: &mut Option<(usize, Option<usize>)>,
+    ) {
+        let selected = self.selection.contains(&id);
+        let egui::InnerResponse { inner, response } =
+            ui.dnd_drag_source(egui::Id::new(("object_tree", id)), id, |ui| {
+                ui.selectable_label(selected, &self.objects[id].name)
+            });
+        if inner.clicked() {
+            self.select(Some(id), ui.input(|i| i.modifiers.shift));
+        }
+        if let Some(dragged) = response.dnd_hover_payload::<usize>() {
+            let color = if scene::is_ancestor(&self.objects, *dragged, id) {
+                ui.visuals().error_fg_color
+            } else {
+                ui.visuals().selection.stroke.color
+            };
+            ui.painter()
+                .rect_stroke(response.rect, 2., egui::Stroke::new(1., color));
+        }
+        if let Some(dragged) = response.dnd_release_payload::<usize>() {
+            *reparent = Some((*dragged, Some(id)));
+        }
+
+        let children = scene::children(&self.objects, Some(id));
+        if !children.is_empty() {
+            ui.indent(("object_tree", id), |ui| {
+                for child in children {
+                    self.object_tree_node(ui, child, reparent);
+                }
+            });
+        }
     }
 
     fn model_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Asset root");
+            ui.text_edit_singleline(&mut self.asset_root);
+        });
         ui.text_edit_singleline(&mut self.model_source);
+        ui.collapsing("Repair on load", |ui| {
+            ui.checkbox(&mut self.repair_options.pad_colors, "Pad colors");
+            ui.checkbox(
+                &mut self.repair_options.recompute_normals,
+                "Recompute normals",
+            );
+            ui.checkbox(
+                &mut self.repair_options.drop_bad_triangles,
+                "Drop bad triangles",
+            );
+        });
         ui.horizontal(|ui| {
             if ui.button("New Model").clicked() {
-                const URL_BASE: &str = if cfg!(target_arch = "wasm32") {
-                    "."
-                } else {
-                    "https://edwar4rd.github.io/2025S_ICG_HW1"
-                };
-
                 let source = self.model_source.clone();
-                let request = ehttp::Request::get(format!("{}{}", URL_BASE, &source));
-                let (tx, rx) = Promise::new();
-                ehttp::fetch(request, move |response| {
-                    let resource = response.ok().and_then(|res| {
-                        res.text()
-                            .and_then(|text| serde_json::from_str::<ICGJson>(text).ok())
-                    });
-                    tx.send(resource);
-                });
+                let rx = loading::load_model_source(&self.asset_root, &source, self.repair_options);
                 let new_key = self
                     .models
                     .lock()
@@ -550,6 +789,7 @@ impl DemoApp {
                     CGModel {
                         source,
                         state: ModelState::Loading(rx),
+                        report: None,
                     }
                 });
             }
@@ -561,7 +801,7 @@ impl DemoApp {
             }
         });
 
-        for (id, model) in self.models.lock().iter() {
+        for (id, model) in self.models.lock().iter_mut() {
             ui.horizontal(|ui| {
                 ui.label(format!("{id}"));
                 ui.label(&model.source);
@@ -572,50 +812,346 @@ impl DemoApp {
                     ModelState::Loading(_) => {
                         ui.label("Fetching...");
                     }
-                    ModelState::Failed => {
-                        ui.label("Failed downloading...");
+                    ModelState::Failed(err) => {
+                        ui.label(RichText::new(err.to_string()).color(egui::Color32::RED));
+                        if ui.button("Retry").clicked() {
+                            model.state = ModelState::Loading(loading::load_model_source(
+                                &self.asset_root,
+                                &model.source,
+                                self.repair_options,
+                            ));
+                        }
                     }
                     ModelState::Loaded(id) => {
                         ui.label(format!("Loaded, id {}", id));
+                        if let Some(report) = model.report.as_ref().filter(|r| !r.is_clean()) {
+                            ui.label(RichText::new("⚠").color(egui::Color32::YELLOW))
+                                .on_hover_text(format!("Found {report}"));
+                        }
                     }
                 }
             });
         }
     }
 
-    fn get_scene_data(&self) -> SceneData {
+    fn viewport_settings(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::new("layout", "Layout")
+            .selected_text(Into::<&'static str>::into(self.layout))
+            .show_ui(ui, |ui| {
+                for layout in Layout::iter() {
+                    ui.selectable_value(
+                        &mut self.layout,
+                        layout,
+                        Into::<&'static str>::into(layout),
+                    );
+                }
+            });
+        let visible = self.layout.pane_count();
+        for (id, pane) in self.panes.iter_mut().enumerate().take(visible) {
+            egui::CollapsingHeader::new(&pane.name)
+                .id_salt(("pane", id))
+                .show(ui, |ui| {
+                    ui.push_id(("pane", id), |ui| pane.ui(ui));
+                });
+        }
+    }
+
+    /// What `pane` sees, the main camera or its own.
+    fn get_scene_data(&self, pane: &Pane) -> SceneData {
+        let clipping = clipping::equations(&self.clipping_planes);
+        let (pose, projection) = if pane.main_camera {
+            (self.camera_pose(), self.projection)
+        } else {
+            (pane.pose, pane.projection)
+        };
         SceneData {
-            objs: self.objects.iter().map(|obj| obj.to_rendered()).collect(),
+            objs: self
+                .objects
+                .iter()
+                .zip(scene::world_matrices(&self.objects))
+                .enumerate()
+                .map(|(id, (obj, world))| {
+                    let mut rendered = obj.to_rendered(world, &clipping);
+                    if let Some(mode) = pane.shading {
+                        rendered.mode = mode as i32;
+                    }
+                    rendered.selected = self.selection.contains(&id);
+                    rendered
+                })
+                .collect(),
             ambient: self.ambient,
-            ambient_ka: self.ambient_ka,
-            camera_pos: self.camera_pos,
-            camera_phi: self.camera_phi,
-            camera_up: self.camera_up,
-            fovy: self.fovy,
+            camera_pos: pose.pos,
+            camera_phi: pose.phi,
+            camera_up: pose.up,
+            fovy: pose.fovy,
+            projection,
+            lights: self.lights.clone(),
             show_lights: self.show_lights,
             light_depth: self.light_depth,
-            clipping_pos: self.clipping_pos,
-            clipping_vec: self.clipping_vec,
+            clipping_mode: self.clipping_mode,
+            highlight_clipped_edges: self.highlight_clipped_edges,
+            capping: self.capping,
+            observed: (pane.observer && !pane.main_camera)
+                .then(|| self.main_clip_matrix())
+                .flatten(),
         }
     }
 
-    fn custom_painting(&mut self, ui: &mut egui::Ui) {
-        let (rect, response) = ui.allocate_exact_size(ui.available_size(), egui::Sense::drag());
+    fn camera_pose(&self) -> CameraPose {
+        CameraPose {
+            pos: self.camera_pos,
+            phi: self.camera_phi,
+            up: self.camera_up,
+            fovy: self.fovy,
+        }
+    }
 
+    /// Moves the camera to `pose`, with the orbit target `target_distance` in
+    /// front of it.
+    fn set_camera_pose(&mut self, pose: CameraPose, target_distance: f32) {
+        self.camera_pos = pose.pos;
+        self.camera_phi = pose.phi;
+        self.camera_up = pose.up;
+        self.fovy = pose.fovy;
+        if self.camera_mode != CameraMode::Free {
+            self.camera_target = pose.pos + camera::forward(pose.phi, pose.up) * target_distance;
+        }
+    }
+
+    fn fly_to(&mut self, pose: CameraPose, target_distance: f32) {
+        self.flight = Some(Flight::new(self.camera_pose(), pose, target_distance));
+    }
+
+    fn go_to_bookmark(&mut self, index: usize) {
+        if let Some(pose) = self.bookmarks.get(index).map(|bookmark| bookmark.pose) {
+            let distance = self.orbit().distance();
+            self.fly_to(pose, distance);
+        }
+    }
+
+    /// Flies to a view of the selected object's bounds, keeping the current
+    /// view direction.
+    fn frame_selected(&mut self) {
+        let Some(id) = self.selected_object.filter(|&id| id < self.objects.len()) else {
+            return;
+        };
+        let (obj, world) = (&self.objects[id], scene::world_matrices(&self.objects)[id]);
+        let Some(corners) = self.gl_stuff.lock().as_ref().map(|stuff| {
+            stuff
+                .model(obj.model_id.map(|(_self_id, gl_id)| gl_id))
+                .corners(world)
+                .collect::<Vec<_>>()
+        }) else {
+            return;
+        };
+        if let Some((pose, distance, ortho_size)) =
+            camera::frame_points(self.camera_pose(), corners)
         {
-            let selected_obj = self
-                .selected_object
-                .and_then(|obj_id| self.objects.get_mut(obj_id))
-                .unwrap_or(&mut self.dummy_object);
-            selected_obj.translation.x += response.drag_motion().x * 0.01;
-            selected_obj.translation.y += response.drag_motion().y * -0.01;
+            if self.projection.kind == ProjectionKind::Orthographic {
+                self.projection.ortho_size = ortho_size;
+            }
+            self.fly_to(pose, distance);
+        }
+    }
+
+    /// Number keys fly to the bookmarks, F frames the selected object.
+    fn camera_hotkeys(&mut self, ctx: &egui::Context) {
+        use egui::Key;
+        let keys = [
+            Key::Num1,
+            Key::Num2,
+            Key::Num3,
+            Key::Num4,
+            Key::Num5,
+            Key::Num6,
+            Key::Num7,
+            Key::Num8,
+            Key::Num9,
+        ];
+        let (bookmark, frame) = ctx.input(|i| {
+            (
+                keys.iter().position(|key| i.key_pressed(*key)),
+                i.key_pressed(Key::F),
+            )
+        });
+        if let Some(index) = bookmark {
+            self.go_to_bookmark(index);
+        }
+        if frame {
+            self.frame_selected();
+        }
+    }
+
+    fn bookmark_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.bookmark_name);
+            if ui.button("Add Bookmark").clicked() {
+                self.bookmarks.push(CameraBookmark {
+                    name: self.bookmark_name.clone(),
+                    pose: self.camera_pose(),
+                });
+            }
+        });
+
+        let pose = self.camera_pose();
+        let (mut go_to, mut remove) = (None, None);
+        for (index, bookmark) in self.bookmarks.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("Go").clicked() {
+                    go_to = Some(index);
+                }
+                if ui.button("Update").clicked() {
+                    bookmark.pose = pose;
+                }
+                if ui.button("Remove").clicked() {
+                    remove = Some(index);
+                }
+                ui.text_edit_singleline(&mut bookmark.name);
+                if index < 9 {
+                    ui.label(format!("({})", index + 1));
+                }
+            });
+        }
+        if let Some(index) = remove {
+            self.bookmarks.remove(index);
+        }
+        if let Some(index) = go_to {
+            self.go_to_bookmark(index);
+        }
+    }
+
+    fn orbit(&mut self) -> Orbit<'_> {
+        Orbit {
+            pos: &mut self.camera_pos,
+            phi: &mut self.camera_phi,
+            up: &mut self.camera_up,
+            target: &mut self.camera_target,
+        }
+    }
+
+    /// Moves the camera with WASD (relative to where it looks) and QE (world
+    /// up and down), dragging the orbit target along.
+    fn fly(&mut self, ctx: &egui::Context) {
+        let (input, sprint, dt) = ctx.input(|i| {
+            let axis = |positive, negative| {
+                i.key_down(positive) as i32 as f32 - i.key_down(negative) as i32 as f32
+            };
+            (
+                vec3(
+                    axis(egui::Key::D, egui::Key::A),
+                    axis(egui::Key::E, egui::Key::Q),
+                    axis(egui::Key::W, egui::Key::S),
+                ),
+                i.modifiers.shift,
+                // stable_dt can still spike after a stall, don't jump across the scene
+                i.stable_dt.min(0.1),
+            )
+        });
+        if input == Vec3::ZERO {
+            return;
+        }
+        let speed = self.camera_speed * if sprint { SPRINT_FACTOR } else { 1. };
+        let offset = camera::fly_offset(self.camera_phi, self.camera_up, input) * speed * dt;
+        self.camera_pos += offset;
+        if self.camera_mode != CameraMode::Free {
+            self.camera_target += offset;
+        }
+        ctx.request_repaint();
+    }
+
+    fn camera_controls(&mut self, ui: &egui::Ui, rect: egui::Rect, response: &egui::Response) {
+        let delta = Vec2::from(<[f32; 2]>::from(response.drag_delta()));
+        let mode = self.camera_mode;
+        if mode == CameraMode::Free {
+            if response.dragged_by(egui::PointerButton::Secondary) {
+                camera::look(&mut self.camera_phi, &mut self.camera_up, delta);
+            }
+            return;
+        }
+        let distance = self.orbit().distance();
+        let units_per_pixel = self
+            .projection
+            .units_per_pixel(self.fovy, distance, rect.height());
+        let zoom = if response.hovered() {
+            (ui.input(|i| i.smooth_scroll_delta.y) * -0.002).exp()
+        } else {
+            1.
+        };
+
+        let mut orbit = self.orbit();
+        if response.dragged_by(egui::PointerButton::Primary) {
+            match mode {
+                CameraMode::Free => {}
+                CameraMode::Orbit => orbit.orbit(delta),
+                CameraMode::Arcball => {
+                    if let Some(pointer) = response.interact_pointer_pos() {
+                        let to = Vec2::from(<[f32; 2]>::from(pointer - rect.center()));
+                        orbit.arcball(to - delta, to, rect.width().min(rect.height()) / 2.);
+                    }
+                }
+            }
+        }
+        if response.dragged_by(egui::PointerButton::Middle) {
+            orbit.pan(delta, units_per_pixel);
+        }
+        if zoom != 1. {
+            orbit.zoom(zoom);
+            // Moving closer doesn't make anything bigger in orthographic.
+            if self.projection.kind == ProjectionKind::Orthographic {
+                self.projection.ortho_size *= zoom;
+            }
+        }
+    }
+
+    fn custom_painting(&mut self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+        if self.panes.len() < 4 {
+            self.panes.resize_with(4, Default::default);
+        }
+        for (id, pane_rect) in self.layout.split(rect).into_iter().enumerate() {
+            self.paint_pane(ui, id, pane_rect);
+        }
+    }
+
+    fn paint_pane(&mut self, ui: &mut egui::Ui, id: usize, rect: egui::Rect) {
+        let response = ui.interact(
+            rect,
+            ui.id().with(("pane", id)),
+            egui::Sense::click_and_drag(),
+        );
+        if self.panes[id].main_camera {
+            self.main_aspect = rect.aspect_ratio();
+        }
+
+        if !self.gizmo_controls(ui, id, rect, &response) {
+            if response.clicked() {
+                if let Some(pos) = response.interact_pointer_pos() {
+                    let hit = self.pick(id, rect, pos);
+                    self.select(hit, ui.input(|i| i.modifiers.shift));
+                }
+            }
+            // Dragging away from the gizmo with Ctrl still moves the object
+            // the quick way, Ctrl only toggles snapping on the gizmo itself.
+            if ui.input(|i| i.modifiers.command) {
+                let selected_obj = self
+                    .selected_object
+                    .and_then(|obj_id| self.objects.get_mut(obj_id))
+                    .unwrap_or(&mut self.dummy_object);
+                selected_obj.translation.x += response.drag_motion().x * 0.01;
+                selected_obj.translation.y += response.drag_motion().y * -0.01;
+            } else if self.panes[id].main_camera {
+                self.camera_controls(ui, rect, &response);
+            } else {
+                self.panes[id].controls(ui, rect, &response);
+            }
         }
 
         // Clone locals so we can move them into the paint callback:
         // TODO: Optimize this
         let gl_stuff = self.gl_stuff.clone();
         let models = self.models.clone();
-        let scene_data = Arc::new(self.get_scene_data());
+        let scene_data = Arc::new(self.get_scene_data(&self.panes[id]));
+        let frame = ui.ctx().cumulative_pass_nr();
 
         let cb = egui_glow::CallbackFn::new(move |info, painter| {
             let width = info.clip_rect_in_pixels().width_px;
@@ -628,6 +1164,7 @@ impl DemoApp {
                     width,
                     height,
                     scene_data.clone(),
+                    frame,
                     painter.intermediate_fbo(),
                 )
             }
@@ -638,27 +1175,58 @@ impl DemoApp {
             callback: Arc::new(cb),
         };
         ui.painter().add(callback);
+        self.paint_clipping_planes(ui, id, rect);
+        self.paint_observed_frustum(ui, id, rect);
+        self.paint_gizmo(ui, id, rect);
+
+        if self.layout != Layout::Single {
+            let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+            ui.painter().rect_stroke(rect, 0., stroke);
+            ui.painter().text(
+                rect.left_top() + egui::vec2(6., 4.),
+                egui::Align2::LEFT_TOP,
+                &self.panes[id].name,
+                egui::FontId::proportional(14.),
+                ui.visuals().text_color(),
+            );
+        }
     }
 }
 
 struct RenderedObject {
     mv_mat: Mat4,
     mode: i32,
+    material: Material,
     model_id: Option<usize>,
+    selected: bool,
+    /// Every plane equation cutting the object, see
+    /// [`ObjectClipping::equations`].
+    clipping: Vec<Vec4>,
 }
 
 struct SceneData {
     objs: Vec<RenderedObject>,
     ambient: [f32; 3],
-    ambient_ka: f32,
     camera_pos: Vec3,
     camera_up: f32,
     camera_phi: f32,
     fovy: f32,
+    projection: Projection,
+    lights: Vec<Light>,
     show_lights: bool,
     light_depth: bool,
-    clipping_pos: Vec3,
-    clipping_vec: Vec3,
+    clipping_mode: ClippingMode,
+    highlight_clipped_edges: bool,
+    capping: Capping,
+    /// World to clip space of the main camera when an observer pane draws
+    /// it, what is outside is ghosted.
+    observed: Option<Mat4>,
+}
+
+impl SceneData {
+    fn view_matrix(&self) -> Mat4 {
+        camera::view_matrix(self.camera_pos, self.camera_phi, self.camera_up)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -677,6 +1245,13 @@ struct ICGLoaded {
     color_buffer: Buffer,
     norm_buffer: Buffer,
     item_count: i32,
+    /// Corners of the model space bounding box.
+    bounds: [Vec3; 2],
+    /// Model space vertex positions, three per triangle, for picking.
+    positions: Vec<Vec3>,
+    /// Kept along with the positions for clipping on the CPU.
+    normals: Vec<Vec3>,
+    colors: Vec<Vec3>,
 }
 
 impl ICGJson {
@@ -722,12 +1297,57 @@ impl ICGJson {
                 color_buffer,
                 norm_buffer,
                 item_count: self.vertex_positions.len() as i32 / 3,
+                bounds: self.bounds(),
+                positions: self
+                    .vertex_positions
+                    .chunks_exact(3)
+                    .map(Vec3::from_slice)
+                    .collect(),
+                normals: self
+                    .vertex_normals
+                    .chunks_exact(3)
+                    .map(Vec3::from_slice)
+                    .collect(),
+                colors: self
+                    .vertex_frontcolors
+                    .chunks_exact(3)
+                    .map(Vec3::from_slice)
+                    .collect(),
             }
         }
     }
 }
 
+impl ICGJson {
+    fn bounds(&self) -> [Vec3; 2] {
+        let bounds = self
+            .vertex_positions
+            .chunks_exact(3)
+            .map(Vec3::from_slice)
+            .fold([Vec3::INFINITY, Vec3::NEG_INFINITY], |[min, max], p| {
+                [min.min(p), max.max(p)]
+            });
+        if bounds[0].cmple(bounds[1]).all() {
+            bounds
+        } else {
+            [Vec3::ZERO; 2]
+        }
+    }
+}
+
 impl ICGLoaded {
+    /// Corners of the bounding box after transforming it by `mat`.
+    fn corners(&self, mat: Mat4) -> impl Iterator<Item = Vec3> {
+        let [min, max] = self.bounds;
+        (0..8).map(move |i| {
+            mat.transform_point3(Vec3::select(
+                glam::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                max,
+                min,
+            ))
+        })
+    }
+
     fn destroy(&self, gl: &glow::Context) {
         unsafe {
             gl.delete_buffer(self.pos_buffer);
@@ -744,7 +1364,20 @@ struct GLStuff {
     light_vao: VertexArray,
     default_model: ICGLoaded,
     light_model: ICGLoaded,
+    spot_light_model: ICGLoaded,
+    directional_light_model: ICGLoaded,
+    light_buffer: Buffer,
+    shadow_maps: ShadowMaps,
+    /// The egui pass the shadow maps were last rendered in, they only need
+    /// rendering once however often a frame paints the scene.
+    shadow_frame: Option<u64>,
+    outline: Outline,
+    caps: CapRenderer,
+    /// Whether the framebuffer we draw to has a stencil buffer.
+    has_stencil: bool,
     models: BTreeMap<usize, ICGLoaded>,
+    /// Objects clipped on the CPU by index, see [`GLStuff::update_clipped`].
+    clipped: BTreeMap<usize, polygon_clipping::ClippedModel>,
 }
 
 #[allow(unsafe_code)] // we need unsafe code to use glow
@@ -756,22 +1389,27 @@ impl GLStuff {
                 ModelState::Ready => {}
                 ModelState::Loading(promise) => {
                     if let Some(result) = promise.ready() {
-                        if let Some(loaded) = result {
-                            let loaded = loaded.load_model(self.vertex_array, gl);
-                            let new_key = self
-                                .models
-                                .last_key_value()
-                                .map(|(key, _)| *key + 1)
-                                .unwrap_or(0);
-                            self.models.insert(new_key, loaded);
-                            model.state = ModelState::Loaded(new_key);
-                            used_model.insert(new_key);
-                        } else {
-                            model.state = ModelState::Failed;
+                        match result {
+                            Ok((loaded, report)) => {
+                                let loaded = loaded.load_model(self.vertex_array, gl);
+                                model.report = Some(report.clone());
+                                let new_key = self
+                                    .models
+                                    .last_key_value()
+                                    .map(|(key, _)| *key + 1)
+                                    .unwrap_or(0);
+                                self.models.insert(new_key, loaded);
+                                model.state = ModelState::Loaded(new_key);
+                                used_model.insert(new_key);
+                            }
+                            Err(err) => {
+                                log::warn!("Failed loading {}: {err}", model.source);
+                                model.state = ModelState::Failed(err.clone());
+                            }
                         }
                     }
                 }
-                ModelState::Failed => {}
+                ModelState::Failed(_) => {}
                 ModelState::Loaded(id) => {
                     used_model.insert(*id);
                 }
@@ -864,14 +1502,35 @@ impl GLStuff {
                 return None;
             }
 
-            let (vertex_shader_source, fragment_shader_source) =
-                (include_str!("vertex.glsl"), include_str!("fragment.glsl"));
+            // Both stages share the light definitions and the shading function,
+            // only the fragment stage samples the shadow maps.
+            let lighting = format!(
+                "#define MAX_LIGHTS {MAX_LIGHTS}\n{}",
+                include_str!("lighting.glsl")
+            );
+            let (vertex_shader_source, fragment_shader_source) = (
+                format!("{lighting}\n{}", include_str!("vertex.glsl")),
+                format!(
+                    "{}{lighting}\n{}\n{}\n{}",
+                    shadow::shader_defines(),
+                    clipping::shader_source(),
+                    include_str!("fragment.glsl"),
+                    shadow::shader_source()
+                ),
+            );
             let program = Self::create_program(
                 shader_version,
-                vertex_shader_source,
-                fragment_shader_source,
+                &vertex_shader_source,
+                &fragment_shader_source,
                 gl,
             );
+            let lights_index = gl.get_uniform_block_index(program, "Lights").unwrap();
+            gl.uniform_block_binding(program, lights_index, LIGHTS_BINDING);
+            let light_buffer = gl.create_buffer().unwrap();
+            let shadow_maps = ShadowMaps::new(shader_version, gl);
+            let outline = Outline::new(shader_version, gl);
+            let caps = CapRenderer::new(shader_version, gl);
+            let has_stencil = CapRenderer::has_stencil(gl);
 
             let (vertex_shader_source, fragment_shader_source) = (
                 include_str!("vertex_simple.glsl"),
@@ -915,6 +1574,8 @@ impl GLStuff {
             let light_json = include_str!("../model/Light.json");
             let light_json: ICGJson = serde_json::from_str(light_json).unwrap();
             let light_model = light_json.load_model(vertex_array, gl);
+            let spot_light_model = light::spot_gizmo().load_model(vertex_array, gl);
+            let directional_light_model = light::directional_gizmo().load_model(vertex_array, gl);
 
             Some(Self {
                 program,
@@ -923,7 +1584,16 @@ impl GLStuff {
                 light_vao,
                 default_model: teapot_model,
                 light_model,
+                spot_light_model,
+                directional_light_model,
+                light_buffer,
+                shadow_maps,
+                shadow_frame: None,
+                outline,
+                caps,
+                has_stencil,
                 models: BTreeMap::new(),
+                clipped: BTreeMap::new(),
             })
         }
     }
@@ -932,31 +1602,87 @@ impl GLStuff {
         use glow::HasContext as _;
         unsafe {
             gl.delete_program(self.program);
+            gl.delete_buffer(self.light_buffer);
+            self.shadow_maps.destroy(gl);
+            self.outline.destroy(gl);
+            self.caps.destroy(gl);
             self.default_model.destroy(gl);
             self.light_model.destroy(gl);
+            self.spot_light_model.destroy(gl);
+            self.directional_light_model.destroy(gl);
             for model in self.models.values() {
                 model.destroy(gl);
             }
+            for clipped in self.clipped.values() {
+                clipped.destroy(gl);
+            }
         }
     }
 
+    /// The mesh to draw for a model id, falling back to the default model.
+    fn model(&self, model_id: Option<usize>) -> &ICGLoaded {
+        model_id
+            .and_then(|id| self.models.get(&id))
+            .unwrap_or(&self.default_model)
+    }
+
+    /// View to clip space, fitting near and far to the scene if asked to.
+    fn projection_matrix(&self, scene_data: &SceneData, aspect: f32) -> Mat4 {
+        let view_mat = scene_data.view_matrix();
+        let lights = &scene_data.lights[..scene_data.lights.len().min(MAX_LIGHTS)];
+        // Distances along the view direction everything in the scene covers.
+        let depth = scene_data
+            .objs
+            .iter()
+            .flat_map(|obj| self.model(obj.model_id).corners(view_mat * obj.mv_mat))
+            .chain(
+                lights
+                    .iter()
+                    .map(|light| view_mat.transform_point3(light.position)),
+            )
+            .map(|p| -p.z)
+            .fold(None, |range: Option<(f32, f32)>, z| match range {
+                Some((min, max)) => Some((min.min(z), max.max(z))),
+                None => Some((z, z)),
+            })
+            .filter(|&(_, max)| max > 0.);
+        scene_data.projection.matrix(scene_data.fovy, aspect, depth)
+    }
+
     fn paint(
-        &self,
+        &mut self,
         gl: &glow::Context,
         width: i32,
         height: i32,
         scene_data: Arc<SceneData>,
+        frame: u64,
         intermediate_fbo: Option<glow::Framebuffer>,
     ) {
         use glow::HasContext as _;
-        let perspective_mat = Mat4::perspective_rh_gl(
-            scene_data.fovy.to_radians(),
-            width as f32 / height as f32,
-            0.1,
-            100.0,
-        ) * Mat4::from_rotation_x(-scene_data.camera_up.to_radians())
-            * Mat4::from_rotation_y(scene_data.camera_phi.to_radians())
-            * Mat4::from_translation(-scene_data.camera_pos);
+        let lights = &scene_data.lights[..scene_data.lights.len().min(MAX_LIGHTS)];
+        let perspective_mat = self.projection_matrix(&scene_data, width as f32 / height as f32)
+            * scene_data.view_matrix();
+
+        let render_shadows = self.shadow_frame != Some(frame);
+        if render_shadows {
+            self.shadow_frame = Some(frame);
+            self.shadow_maps.prepare(gl, lights);
+        }
+        self.update_clipped(gl, &scene_data);
+        // What each object is drawn as and the planes left for the GPU.
+        let meshes: Vec<_> = scene_data
+            .objs
+            .iter()
+            .enumerate()
+            .map(|(id, obj)| {
+                let (model, planes) = self.clipped_mesh(id, obj);
+                (obj.mv_mat, model, planes)
+            })
+            .collect();
+        if render_shadows {
+            self.shadow_maps
+                .render(gl, lights, &meshes, intermediate_fbo);
+        }
 
         unsafe {
             gl.use_program(Some(self.program));
@@ -968,43 +1694,39 @@ impl GLStuff {
             let p_mat_loc = gl.get_uniform_location(self.program, "uPMatrix").unwrap();
             let mv_mat_loc = gl.get_uniform_location(self.program, "uMVMatrix").unwrap();
 
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.program, "clipping_plane_pos")
-                    .as_ref(),
-                &scene_data.clipping_pos.to_array(),
-            );
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.program, "clipping_plane")
-                    .as_ref(),
-                &scene_data.clipping_vec.to_array(),
-            );
             gl.uniform_matrix_4_f32_slice(
                 Some(&p_mat_loc),
                 false,
                 &perspective_mat.to_cols_array(),
             );
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.program, "lightLoc").as_ref(),
-                &[0., 5., 5., 17., 5., -2., -17., 5., -2.],
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(self.light_buffer));
+            gl.buffer_data_u8_slice(
+                glow::UNIFORM_BUFFER,
+                bytemuck::bytes_of(&LightBlock::new(lights)),
+                glow::DYNAMIC_DRAW,
             );
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.program, "lightColor").as_ref(),
-                &[1., 1., 0., 1., 0., 1., 0., 1., 1.],
-            );
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.program, "lightKdKsCD")
-                    .as_ref(),
-                &[0.6, 0.3, 20.0, 0.6, 0.3, 20.0, 0.6, 0.3, 20.0],
+            gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+            gl.bind_buffer_base(
+                glow::UNIFORM_BUFFER,
+                LIGHTS_BINDING,
+                Some(self.light_buffer),
             );
             gl.uniform_3_f32_slice(
                 gl.get_uniform_location(self.program, "ambient_color")
                     .as_ref(),
                 &scene_data.ambient,
             );
-            gl.uniform_1_f32(
-                gl.get_uniform_location(self.program, "Ka").as_ref(),
-                scene_data.ambient_ka,
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "observing").as_ref(),
+                scene_data.observed.is_some() as i32,
             );
+            gl.uniform_matrix_4_f32_slice(
+                gl.get_uniform_location(self.program, "uObservedMatrix")
+                    .as_ref(),
+                false,
+                &scene_data.observed.unwrap_or_default().to_cols_array(),
+            );
+            self.shadow_maps.bind(gl, self.program);
 
             let vertex_position_loc = gl
                 .get_attrib_location(self.program, "aVertexPosition")
@@ -1014,17 +1736,22 @@ impl GLStuff {
                 .get_attrib_location(self.program, "aVertexNormal")
                 .unwrap();
 
-            for obj in scene_data.objs.iter() {
-                let obj_model = if let Some(id) = obj.model_id {
-                    if let Some(model) = self.models.get(&id) {
-                        model
-                    } else {
-                        &self.default_model
-                    }
-                } else {
-                    &self.default_model
-                };
+            // The outline goes between the other objects and the selected
+            // ones, so it shows wherever nothing is in front of it but the
+            // selected objects themselves cover it.
+            let (selected, others): (Vec<_>, Vec<_>) =
+                (0..scene_data.objs.len()).partition(|&id| scene_data.objs[id].selected);
+            for (i, &id) in others.iter().chain(&selected).enumerate() {
+                if i == others.len() {
+                    let outlined: Vec<_> = selected.iter().map(|&id| meshes[id]).collect();
+                    self.outline
+                        .render(gl, &outlined, perspective_mat, width, height);
+                    gl.use_program(Some(self.program));
+                }
+                let obj = &scene_data.objs[id];
+                let (_, obj_model, clipping_planes) = meshes[id];
 
+                clipping::set_uniforms(gl, self.program, clipping_planes);
                 gl.uniform_matrix_4_f32_slice(
                     Some(&mv_mat_loc),
                     false,
@@ -1035,6 +1762,24 @@ impl GLStuff {
                     gl.get_uniform_location(self.program, "mode").as_ref(),
                     obj.mode,
                 );
+                let material = &obj.material;
+                gl.uniform_4_f32(
+                    gl.get_uniform_location(self.program, "material").as_ref(),
+                    material.ka,
+                    material.kd,
+                    material.ks,
+                    material.shininess,
+                );
+                gl.uniform_3_f32_slice(
+                    gl.get_uniform_location(self.program, "materialColor")
+                        .as_ref(),
+                    &material.base_color,
+                );
+                gl.uniform_1_i32(
+                    gl.get_uniform_location(self.program, "useVertexColor")
+                        .as_ref(),
+                    material.use_vertex_colors as i32,
+                );
 
                 gl.bind_vertex_array(Some(self.vertex_array));
                 gl.bind_framebuffer(glow::FRAMEBUFFER, intermediate_fbo);
@@ -1049,15 +1794,32 @@ impl GLStuff {
                 gl.bind_vertex_array(None);
             }
 
+            if self.has_stencil {
+                // Clipping on the CPU leaves the same surfaces the planes
+                // would, so the caps still find the inside with them.
+                let capped: Vec<_> = meshes
+                    .iter()
+                    .zip(&scene_data.objs)
+                    .map(|(&(mv_mat, model, _), obj)| (mv_mat, model, &obj.clipping[..]))
+                    .collect();
+                self.caps
+                    .render(gl, &capped, perspective_mat, &scene_data.capping);
+            }
+            if scene_data.highlight_clipped_edges {
+                self.paint_clipped_edges(gl, &scene_data, perspective_mat);
+            }
+
             if !scene_data.light_depth {
                 gl.disable(glow::DEPTH_TEST);
             }
 
             if scene_data.show_lights {
-                let light_pos = [vec3(0., 5., 5.), vec3(17., 5., -2.), vec3(-17., 5., -2.)];
-                let light_color = [vec3(1., 1., 0.), vec3(1., 0., 1.), vec3(0., 1., 1.)];
-
-                for light_id in 0..3 {
+                for light in lights {
+                    let light_model = match light.kind {
+                        LightKind::Directional => &self.directional_light_model,
+                        LightKind::Point => &self.light_model,
+                        LightKind::Spot => &self.spot_light_model,
+                    };
                     gl.use_program(Some(self.light_program));
                     let p_mat_loc = gl
                         .get_uniform_location(self.light_program, "uPMatrix")
@@ -1076,7 +1838,7 @@ impl GLStuff {
                     gl.uniform_matrix_4_f32_slice(
                         Some(&mv_mat_loc),
                         false,
-                        &Mat4::from_translation(light_pos[light_id]).to_cols_array(),
+                        &light.gizmo_matrix().to_cols_array(),
                     );
                     gl.uniform_matrix_4_f32_slice(
                         Some(&p_mat_loc),
@@ -1086,17 +1848,17 @@ impl GLStuff {
                     gl.uniform_3_f32_slice(
                         gl.get_uniform_location(self.light_program, "ambient_color")
                             .as_ref(),
-                        &light_color[light_id].to_array(),
+                        &light.color,
                     );
                     gl.uniform_1_f32(
                         gl.get_uniform_location(self.light_program, "Ka").as_ref(),
                         1.0,
                     );
-                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.light_model.pos_buffer));
+                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(light_model.pos_buffer));
                     gl.vertex_attrib_pointer_f32(vertex_position_loc, 3, glow::FLOAT, false, 0, 0);
-                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.light_model.color_buffer));
+                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(light_model.color_buffer));
                     gl.vertex_attrib_pointer_f32(front_color_loc, 3, glow::FLOAT, false, 0, 0);
-                    gl.draw_arrays(glow::TRIANGLES, 0, self.light_model.item_count);
+                    gl.draw_arrays(glow::TRIANGLES, 0, light_model.item_count);
                     gl.bind_vertex_array(None);
                 }
             }
@@ -1107,3 +1869,65 @@ impl GLStuff {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `obj` as RON, with its shear written the way it was before the six
+    /// shear factors.
+    fn old_object_ron(obj: &CGObject, angles: &str) -> String {
+        let ron = ron::to_string(obj).unwrap();
+        let start = ron.find("shear_factors:(").unwrap();
+        let end = start + ron[start..].find(')').unwrap() + 1;
+        format!("{}shear:{angles}{}", &ron[..start], &ron[end..])
+    }
+
+    #[test]
+    fn old_shear_angles_are_migrated() {
+        let obj = old_object_ron(&CGObject::default(), "(45.0,90.0,90.0)");
+        let mut app: DemoApp = ron::from_str(&format!("(objects:[{obj}],asset_root:\"old\")"))
+            .expect("old state should load");
+        app.migrate();
+        assert_eq!(app.asset_root, "old");
+        let shear = app.objects[0].shear;
+        assert!((shear.xy - 1.).abs() < 1e-5, "{shear:?}");
+        assert!(shear.yz.abs() < 1e-5 && shear.zx.abs() < 1e-5, "{shear:?}");
+        assert!(app.objects[0].shear_angles.is_none());
+    }
+
+    #[test]
+    fn old_clipping_plane_is_migrated() {
+        let mut app: DemoApp = ron::from_str(
+            "(clipping_pos:(1.0,2.0,3.0),clipping_vec:(0.0,0.0,-1.0),asset_root:\"old\")",
+        )
+        .expect("old state should load");
+        app.migrate();
+        assert_eq!(app.asset_root, "old");
+        assert_eq!(app.clipping_planes.len(), 1);
+        let plane = &app.clipping_planes[0];
+        assert_eq!((plane.pos, plane.normal), (vec3(1., 2., 3.), -Vec3::Z));
+        assert!(plane.enabled);
+        assert_eq!((app.clipping_pos, app.clipping_vec), (None, None));
+    }
+
+    #[test]
+    fn clipping_planes_round_trip() {
+        let mut app = DemoApp::default();
+        app.clipping_planes[0].pos = vec3(0., 1., 2.);
+        let ron = ron::to_string(&app).unwrap();
+        assert!(!ron.contains("clipping_pos"));
+        let mut loaded: DemoApp = ron::from_str(&ron).unwrap();
+        loaded.migrate();
+        assert_eq!(loaded.clipping_planes, app.clipping_planes);
+    }
+
+    #[test]
+    fn shear_angles_are_not_saved() {
+        let ron = ron::to_string(&CGObject::default()).unwrap();
+        assert!(ron.contains("shear_factors:"));
+        assert!(!ron.contains("shear:"));
+        let obj: CGObject = ron::from_str(&ron).unwrap();
+        assert_eq!(obj.shear_angles, None);
+    }
+}
@@ -0,0 +1,484 @@
+use egui::Slider;
+use glam::{vec3, Mat4, Quat, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
+
+/// How mouse input in the viewport moves the camera.
+#[derive(
+    Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize, Default, IntoStaticStr, EnumIter,
+)]
+pub(super) enum CameraMode {
+    /// Fly around with the keyboard, look around by dragging with the right
+    /// mouse button.
+    #[default]
+    Free,
+    /// Dragging turns the camera around the target, keeping it upright.
+    Orbit,
+    /// Dragging rolls a virtual ball around the target.
+    Arcball,
+}
+
+#[derive(
+    Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize, Default, IntoStaticStr, EnumIter,
+)]
+pub(super) enum ProjectionKind {
+    #[default]
+    Perspective,
+    Orthographic,
+}
+
+/// View to clip space settings, the field of view lives with the camera.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Projection {
+    pub(super) kind: ProjectionKind,
+    /// Height of the orthographic view volume.
+    pub(super) ortho_size: f32,
+    pub(super) near: f32,
+    pub(super) far: f32,
+    /// Fit near and far around the scene every frame instead.
+    pub(super) auto_near_far: bool,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            kind: Default::default(),
+            ortho_size: 20.,
+            near: 0.1,
+            far: 100.,
+            auto_near_far: false,
+        }
+    }
+}
+
+impl Projection {
+    pub(super) fn ui(&mut self, ui: &mut egui::Ui, fovy: &mut f32) {
+        egui::ComboBox::new("projection_kind", "Projection")
+            .selected_text(Into::<&'static str>::into(self.kind))
+            .show_ui(ui, |ui| {
+                for kind in ProjectionKind::iter() {
+                    ui.selectable_value(&mut self.kind, kind, Into::<&'static str>::into(kind));
+                }
+            });
+        match self.kind {
+            ProjectionKind::Perspective => {
+                ui.add(Slider::new(fovy, 0.0..=180.).text("fov"));
+            }
+            ProjectionKind::Orthographic => {
+                ui.add(
+                    Slider::new(&mut self.ortho_size, 0.1..=500.)
+                        .logarithmic(true)
+                        .text("size"),
+                );
+            }
+        }
+        ui.checkbox(&mut self.auto_near_far, "Fit near/far to the scene");
+        ui.add_enabled_ui(!self.auto_near_far, |ui| {
+            ui.add(
+                Slider::new(&mut self.near, 0.01..=100.)
+                    .logarithmic(true)
+                    .text("near"),
+            );
+            ui.add(
+                Slider::new(&mut self.far, 1.0..=10000.)
+                    .logarithmic(true)
+                    .text("far"),
+            );
+        });
+        self.far = self.far.max(self.near * 1.01);
+    }
+
+    /// View to clip space for a viewport with the given aspect ratio. `depth`
+    /// is the range of view distances the scene covers, used when fitting
+    /// near and far.
+    pub(super) fn matrix(&self, fovy: f32, aspect: f32, depth: Option<(f32, f32)>) -> Mat4 {
+        let (near, far) = match depth {
+            Some((min, max)) if self.auto_near_far => {
+                let far = max * 1.01 + 0.01;
+                let near = match self.kind {
+                    // Keep the ratio sane for the depth buffer.
+                    ProjectionKind::Perspective => (min * 0.99).max(far * 1e-4),
+                    // Things behind the camera are fine in orthographic.
+                    ProjectionKind::Orthographic => min - 0.01 - 0.01 * min.abs(),
+                };
+                (near, far.max(near + 0.01))
+            }
+            _ => (self.near, self.far),
+        };
+        match self.kind {
+            ProjectionKind::Perspective => {
+                Mat4::perspective_rh_gl(fovy.to_radians(), aspect, near, far)
+            }
+            ProjectionKind::Orthographic => {
+                let (h, w) = (self.ortho_size / 2., self.ortho_size / 2. * aspect);
+                Mat4::orthographic_rh_gl(-w, w, -h, h, near, far)
+            }
+        }
+    }
+
+    /// World units per pixel at `distance` from the camera.
+    pub(super) fn units_per_pixel(&self, fovy: f32, distance: f32, viewport_height: f32) -> f32 {
+        let height = match self.kind {
+            ProjectionKind::Perspective => 2. * distance * (fovy.to_radians() / 2.).tan(),
+            ProjectionKind::Orthographic => self.ortho_size,
+        };
+        height / viewport_height.max(1.)
+    }
+}
+
+/// Pitch is kept short of straight up or down so phi stays meaningful.
+pub(super) const MAX_PITCH: f32 = 89.;
+
+/// Degrees turned per pixel dragged in orbit mode.
+const ORBIT_SPEED: f32 = 0.3;
+
+/// Degrees turned per pixel dragged when looking around in free mode.
+const LOOK_SPEED: f32 = 0.2;
+
+/// How much faster the camera flies while sprinting.
+pub(super) const SPRINT_FACTOR: f32 = 4.;
+
+/// Closest the camera gets to the target when zooming.
+const MIN_DISTANCE: f32 = 0.1;
+
+/// Direction the camera looks in, for the given `camera_phi` and `camera_up`
+/// in degrees.
+pub(super) fn forward(phi: f32, up: f32) -> Vec3 {
+    let (phi, up) = (phi.to_radians(), up.to_radians());
+    vec3(up.cos() * phi.sin(), up.sin(), -up.cos() * phi.cos())
+}
+
+/// The inverse of [`forward`], `camera_phi` and `camera_up` looking along
+/// `direction`.
+pub(super) fn angles(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize_or(Vec3::NEG_Z);
+    (
+        direction.x.atan2(-direction.z).to_degrees(),
+        direction.y.clamp(-1., 1.).asin().to_degrees(),
+    )
+}
+
+/// World to view space.
+pub(super) fn view_matrix(pos: Vec3, phi: f32, up: f32) -> Mat4 {
+    Mat4::from_rotation_x(-up.to_radians())
+        * Mat4::from_rotation_y(phi.to_radians())
+        * Mat4::from_translation(-pos)
+}
+
+/// The camera's right and up vectors in world space.
+pub(super) fn basis(phi: f32, up: f32) -> (Vec3, Vec3) {
+    let view = view_matrix(Vec3::ZERO, phi, up).inverse();
+    (
+        view.transform_vector3(Vec3::X),
+        view.transform_vector3(Vec3::Y),
+    )
+}
+
+/// World space movement for `input`, which holds how far to go right, up
+/// and forward. Up is always world up so Q and E don't depend on the pitch.
+pub(super) fn fly_offset(phi: f32, up: f32, input: Vec3) -> Vec3 {
+    let (right, _) = basis(phi, up);
+    right * input.x + Vec3::Y * input.y + forward(phi, up) * input.z
+}
+
+/// Turns the camera in place by a drag of `delta` pixels.
+pub(super) fn look(phi: &mut f32, up: &mut f32, delta: Vec2) {
+    *phi = (*phi + delta.x * LOOK_SPEED + 540.).rem_euclid(360.) - 180.;
+    *up = (*up - delta.y * LOOK_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+}
+
+/// Orbit (or arcball) camera state derived from the plain camera fields.
+pub(super) struct Orbit<'a> {
+    pub(super) pos: &'a mut Vec3,
+    pub(super) phi: &'a mut f32,
+    pub(super) up: &'a mut f32,
+    pub(super) target: &'a mut Vec3,
+}
+
+impl Orbit<'_> {
+    pub(super) fn distance(&self) -> f32 {
+        (*self.target - *self.pos).length().max(MIN_DISTANCE)
+    }
+
+    /// Puts the camera back on its sphere around the target after the angles
+    /// changed.
+    fn look_at_target(&mut self, distance: f32) {
+        *self.pos = *self.target - forward(*self.phi, *self.up) * distance;
+    }
+
+    /// Turns the camera towards the target, for when orbiting starts.
+    pub(super) fn aim(&mut self) {
+        let (phi, up) = angles(*self.target - *self.pos);
+        *self.phi = phi;
+        *self.up = up.clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Turns the camera around the target by a drag of `delta` pixels.
+    pub(super) fn orbit(&mut self, delta: Vec2) {
+        let distance = self.distance();
+        *self.phi = (*self.phi + delta.x * ORBIT_SPEED + 540.).rem_euclid(360.) - 180.;
+        *self.up = (*self.up - delta.y * ORBIT_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+        self.look_at_target(distance);
+    }
+
+    /// Rolls the arcball from `from` to `to`, both in pixels relative to the
+    /// center of a viewport with the given `radius`.
+    ///
+    /// The camera has no roll, so only the direction it ends up looking in is
+    /// kept.
+    pub(super) fn arcball(&mut self, from: Vec2, to: Vec2, radius: f32) {
+        let distance = self.distance();
+        let (right, up) = basis(*self.phi, *self.up);
+        let back = right.cross(up);
+        let to_world = |p: Vec2| {
+            let p = arcball_point(p / radius);
+            right * p.x + up * p.y + back * p.z
+        };
+        // Dragging the ball one way turns the camera the other way around it.
+        let rotation = Quat::from_rotation_arc(to_world(to), to_world(from));
+        let (phi, pitch) = angles(rotation * forward(*self.phi, *self.up));
+        *self.phi = phi;
+        *self.up = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        self.look_at_target(distance);
+    }
+
+    /// Moves both the camera and the target along the view plane by a drag
+    /// of `delta` pixels.
+    pub(super) fn pan(&mut self, delta: Vec2, units_per_pixel: f32) {
+        let (right, up) = basis(*self.phi, *self.up);
+        let offset = (-right * delta.x + up * delta.y) * units_per_pixel;
+        *self.pos += offset;
+        *self.target += offset;
+    }
+
+    /// Scales the distance to the target by `factor`.
+    pub(super) fn zoom(&mut self, factor: f32) {
+        let distance = (self.distance() * factor).max(MIN_DISTANCE);
+        self.look_at_target(distance);
+    }
+}
+
+/// Maps a point in [-1, 1]² onto the arcball, a sphere that turns into a
+/// hyperbolic sheet towards the edges so dragging outside it still works.
+fn arcball_point(p: Vec2) -> Vec3 {
+    let p = vec3(p.x, -p.y, 0.);
+    let d = p.length_squared();
+    if d <= 0.5 {
+        vec3(p.x, p.y, (1. - d).sqrt())
+    } else {
+        vec3(p.x, p.y, 0.5 / d.sqrt())
+    }
+    .normalize()
+}
+
+/// Where the camera is and how it looks, everything a bookmark restores.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct CameraPose {
+    pub(super) pos: Vec3,
+    pub(super) phi: f32,
+    pub(super) up: f32,
+    pub(super) fovy: f32,
+}
+
+impl CameraPose {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        // Turn the short way around.
+        let phi = (other.phi - self.phi + 540.).rem_euclid(360.) - 180.;
+        Self {
+            pos: self.pos.lerp(other.pos, t),
+            phi: (self.phi + phi * t + 540.).rem_euclid(360.) - 180.,
+            up: self.up + (other.up - self.up) * t,
+            fovy: self.fovy + (other.fovy - self.fovy) * t,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(super) struct CameraBookmark {
+    pub(super) name: String,
+    pub(super) pose: CameraPose,
+}
+
+/// Seconds a fly-to transition takes.
+const FLIGHT_DURATION: f32 = 0.8;
+
+/// An eased transition between two camera poses.
+pub(super) struct Flight {
+    from: CameraPose,
+    to: CameraPose,
+    /// How far in front of the camera the orbit target stays along the way.
+    pub(super) target_distance: f32,
+    elapsed: f32,
+}
+
+impl Flight {
+    pub(super) fn new(from: CameraPose, to: CameraPose, target_distance: f32) -> Self {
+        Self {
+            from,
+            to,
+            target_distance,
+            elapsed: 0.,
+        }
+    }
+
+    /// Advances the flight by `dt` seconds, returning where the camera is now
+    /// and whether it arrived.
+    pub(super) fn step(&mut self, dt: f32) -> (CameraPose, bool) {
+        self.elapsed += dt;
+        let t = (self.elapsed / FLIGHT_DURATION).min(1.);
+        // Cubic ease in and out.
+        let eased = if t < 0.5 {
+            4. * t * t * t
+        } else {
+            1. - (2. - 2. * t).powi(3) / 2.
+        };
+        (self.from.lerp(&self.to, eased), t >= 1.)
+    }
+}
+
+/// A pose looking at the sphere around `points` from the direction of
+/// `pose`, close enough for the sphere to fill the view. Also returns the
+/// distance to the center of the sphere and the orthographic size that fits
+/// it.
+pub(super) fn frame_points(
+    pose: CameraPose,
+    points: impl IntoIterator<Item = Vec3>,
+) -> Option<(CameraPose, f32, f32)> {
+    let (min, max) =
+        points
+            .into_iter()
+            .fold(None, |bounds: Option<(Vec3, Vec3)>, p| match bounds {
+                Some((min, max)) => Some((min.min(p), max.max(p))),
+                None => Some((p, p)),
+            })?;
+    let center = (min + max) / 2.;
+    let radius = ((max - min).length() / 2.).max(MIN_DISTANCE);
+    let half_fov = (pose.fovy.clamp(1., 179.) / 2.).to_radians();
+    // A little margin so the object doesn't touch the edges.
+    let distance = radius / half_fov.sin() * 1.1;
+    Some((
+        CameraPose {
+            pos: center - forward(pose.phi, pose.up) * distance,
+            ..pose
+        },
+        distance,
+        radius * 2.2,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn pose(pos: Vec3, phi: f32, up: f32) -> CameraPose {
+        CameraPose {
+            pos,
+            phi,
+            up,
+            fovy: 60.,
+        }
+    }
+
+    #[test]
+    fn angles_invert_forward() {
+        for phi in [-170., -90., -30., 0., 45., 120., 179.] {
+            for up in [-MAX_PITCH, -45., 0., 10., MAX_PITCH] {
+                let (phi2, up2) = angles(forward(phi, up));
+                assert!((phi2 - phi).abs() < EPSILON, "{phi} {up} -> {phi2}");
+                assert!((up2 - up).abs() < EPSILON, "{phi} {up} -> {up2}");
+            }
+        }
+    }
+
+    #[test]
+    fn forward_matches_view_matrix() {
+        for (phi, up) in [(0., 0.), (90., 0.), (-135., 30.), (60., -80.)] {
+            let view = view_matrix(vec3(1., 2., 3.), phi, up).inverse();
+            let looking = view.transform_vector3(Vec3::NEG_Z);
+            assert!(looking.abs_diff_eq(forward(phi, up), EPSILON), "{phi} {up}");
+        }
+    }
+
+    #[test]
+    fn orbiting_keeps_the_distance() {
+        let (mut phi, mut up, mut target) = (30., 20., vec3(1., -2., 0.5));
+        let mut pos = target - forward(phi, up) * 5.;
+        let mut orbit = Orbit {
+            pos: &mut pos,
+            phi: &mut phi,
+            up: &mut up,
+            target: &mut target,
+        };
+        orbit.orbit(Vec2::new(40., -25.));
+        assert!((orbit.distance() - 5.).abs() < EPSILON);
+        orbit.arcball(Vec2::new(10., 20.), Vec2::new(-60., 35.), 200.);
+        assert!((orbit.distance() - 5.).abs() < EPSILON);
+    }
+
+    #[test]
+    fn arcball_without_motion_stays_put() {
+        let (mut phi, mut up, mut target) = (-70., 35., Vec3::ZERO);
+        let mut pos = target - forward(phi, up) * 3.;
+        let before = (pos, phi, up);
+        let mut orbit = Orbit {
+            pos: &mut pos,
+            phi: &mut phi,
+            up: &mut up,
+            target: &mut target,
+        };
+        let p = Vec2::new(25., -40.);
+        orbit.arcball(p, p, 150.);
+        assert!(pos.abs_diff_eq(before.0, EPSILON));
+        assert!((phi - before.1).abs() < EPSILON);
+        assert!((up - before.2).abs() < EPSILON);
+    }
+
+    #[test]
+    fn flight_goes_from_start_to_end() {
+        let from = pose(Vec3::ZERO, 10., 0.);
+        let to = pose(vec3(10., 0., 0.), 50., 20.);
+
+        let (start, arrived) = Flight::new(from, to, 1.).step(0.);
+        assert!(!arrived);
+        assert!(start.pos.abs_diff_eq(from.pos, EPSILON));
+        assert!((start.phi - from.phi).abs() < EPSILON);
+
+        let mut flight = Flight::new(from, to, 1.);
+        let mut last = 0.;
+        for _ in 0..20 {
+            let (pose, arrived) = flight.step(FLIGHT_DURATION / 40.);
+            assert!(!arrived);
+            assert!(pose.pos.x > last);
+            last = pose.pos.x;
+        }
+        let (end, arrived) = flight.step(FLIGHT_DURATION / 2.);
+        assert!(arrived);
+        assert!(end.pos.abs_diff_eq(to.pos, EPSILON));
+        assert!((end.phi - to.phi).abs() < EPSILON);
+        assert!((end.up - to.up).abs() < EPSILON);
+    }
+
+    #[test]
+    fn flight_arrives_after_its_duration() {
+        let from = pose(Vec3::ZERO, 0., 0.);
+        let to = pose(Vec3::ONE, 0., 0.);
+        let mut flight = Flight::new(from, to, 1.);
+        assert!(!flight.step(FLIGHT_DURATION / 2.).1);
+        assert!(flight.step(FLIGHT_DURATION / 2.).1);
+    }
+
+    #[test]
+    fn framing_needs_points() {
+        let pose = pose(Vec3::ZERO, 30., 10.);
+        assert!(frame_points(pose, []).is_none());
+
+        let (framed, distance, ortho_size) = frame_points(pose, [vec3(1., 2., 3.)]).unwrap();
+        assert!(framed.pos.is_finite());
+        assert!(distance.is_finite() && distance > 0.);
+        assert!(ortho_size.is_finite() && ortho_size > 0.);
+        assert!((framed.pos + forward(30., 10.) * distance).abs_diff_eq(vec3(1., 2., 3.), EPSILON));
+    }
+}
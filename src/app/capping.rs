@@ -0,0 +1,232 @@
+use eframe::{egui_glow::ShaderVersion, glow};
+use glam::{Mat4, Vec3, Vec4};
+use glow::HasContext as _;
+use serde::{Deserialize, Serialize};
+
+use super::{clipping, GLStuff, ICGLoaded};
+
+/// How the cut surface of clipped models is filled in.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Capping {
+    pub(super) enabled: bool,
+    pub(super) color: [f32; 3],
+    /// Diagonal stripes over the color.
+    pub(super) hatch: bool,
+}
+
+impl Default for Capping {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: [0.8, 0.25, 0.2],
+            hatch: true,
+        }
+    }
+}
+
+impl Capping {
+    /// `has_stencil` is whether the framebuffer we draw to has a stencil
+    /// buffer, capping can't work without one.
+    pub(super) fn ui(&mut self, ui: &mut egui::Ui, has_stencil: bool) {
+        ui.add_enabled_ui(has_stencil, |ui| {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.enabled, "Cap Cross-sections");
+                ui.color_edit_button_rgb(&mut self.color);
+                ui.checkbox(&mut self.hatch, "Hatch");
+            });
+        });
+        if !has_stencil {
+            ui.label("Capping needs a stencil buffer, which this context doesn't have.");
+        }
+        ui.label("Only closed models get a proper cap.");
+    }
+}
+
+/// Fills in where clipping planes cut through models, using the stencil
+/// buffer to find the inside of each model on the plane.
+pub(super) struct CapRenderer {
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+    /// Two triangles covering -1..1 in x and y.
+    quad_buffer: glow::Buffer,
+}
+
+#[allow(unsafe_code)] // we need unsafe code to use glow
+impl CapRenderer {
+    pub(super) fn new(shader_version: ShaderVersion, gl: &glow::Context) -> Self {
+        unsafe {
+            let program = GLStuff::create_program(
+                shader_version,
+                include_str!("../cap_vertex.glsl"),
+                &format!(
+                    "{}\n{}",
+                    clipping::shader_source(),
+                    include_str!("../cap_fragment.glsl")
+                ),
+                gl,
+            );
+
+            let vertex_array = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vertex_array));
+            let vertex_position_loc = gl.get_attrib_location(program, "aVertexPosition").unwrap();
+            gl.enable_vertex_attrib_array(vertex_position_loc);
+            gl.bind_vertex_array(None);
+
+            let quad_buffer = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(quad_buffer));
+            let quad: [f32; 18] = [
+                -1., -1., 0., 1., -1., 0., 1., 1., 0., //
+                -1., -1., 0., 1., 1., 0., -1., 1., 0.,
+            ];
+            gl.buffer_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                bytemuck::cast_slice(&quad),
+                glow::STATIC_DRAW,
+            );
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+            Self {
+                program,
+                vertex_array,
+                quad_buffer,
+            }
+        }
+    }
+
+    pub(super) fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.vertex_array);
+            gl.delete_buffer(self.quad_buffer);
+        }
+    }
+
+    /// Whether the framebuffer currently bound has any stencil bits.
+    pub(super) fn has_stencil(gl: &glow::Context) -> bool {
+        unsafe {
+            let kind = gl.get_framebuffer_attachment_parameter_i32(
+                glow::FRAMEBUFFER,
+                glow::STENCIL,
+                glow::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE,
+            );
+            kind != glow::NONE as i32
+                && gl.get_framebuffer_attachment_parameter_i32(
+                    glow::FRAMEBUFFER,
+                    glow::STENCIL,
+                    glow::FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE,
+                ) > 0
+        }
+    }
+
+    /// Caps every object (model matrix, mesh and clipping planes) on each of
+    /// its planes. Expects the objects to already be drawn, with
+    /// depth, and leaves its own program in use.
+    pub(super) fn render(
+        &self,
+        gl: &glow::Context,
+        objects: &[(Mat4, &ICGLoaded, &[Vec4])],
+        perspective_mat: Mat4,
+        capping: &Capping,
+    ) {
+        if !capping.enabled || objects.iter().all(|(_, _, planes)| planes.is_empty()) {
+            return;
+        }
+
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.uniform_matrix_4_f32_slice(
+                gl.get_uniform_location(self.program, "uPMatrix").as_ref(),
+                false,
+                &perspective_mat.to_cols_array(),
+            );
+            gl.uniform_3_f32_slice(
+                gl.get_uniform_location(self.program, "uColor").as_ref(),
+                &capping.color,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "hatch").as_ref(),
+                capping.hatch as i32,
+            );
+            let mv_mat_loc = gl.get_uniform_location(self.program, "uMVMatrix");
+            let vertex_position_loc = gl
+                .get_attrib_location(self.program, "aVertexPosition")
+                .unwrap();
+
+            gl.bind_vertex_array(Some(self.vertex_array));
+            gl.enable(glow::STENCIL_TEST);
+            gl.stencil_mask(1);
+            for (mv_mat, model, clipping_planes) in objects {
+                for (i, plane) in clipping_planes.iter().enumerate() {
+                    // Every surface left after clipping by this plane alone
+                    // flips the stencil, so an odd count means the ray went
+                    // through the cut.
+                    gl.clear_stencil(0);
+                    gl.clear(glow::STENCIL_BUFFER_BIT);
+                    clipping::set_uniforms(gl, self.program, &[*plane]);
+                    gl.color_mask(false, false, false, false);
+                    gl.depth_mask(false);
+                    gl.disable(glow::DEPTH_TEST);
+                    gl.stencil_func(glow::ALWAYS, 0, 1);
+                    gl.stencil_op(glow::KEEP, glow::KEEP, glow::INVERT);
+                    gl.uniform_matrix_4_f32_slice(
+                        mv_mat_loc.as_ref(),
+                        false,
+                        &mv_mat.to_cols_array(),
+                    );
+                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(model.pos_buffer));
+                    gl.vertex_attrib_pointer_f32(vertex_position_loc, 3, glow::FLOAT, false, 0, 0);
+                    gl.draw_arrays(glow::TRIANGLES, 0, model.item_count);
+
+                    // The cap itself is only cut by the other planes.
+                    let others: Vec<_> = clipping_planes
+                        .iter()
+                        .enumerate()
+                        .filter(|&(j, _)| j != i)
+                        .map(|(_, plane)| *plane)
+                        .collect();
+                    clipping::set_uniforms(gl, self.program, &others);
+                    gl.color_mask(true, true, true, true);
+                    gl.depth_mask(true);
+                    gl.enable(glow::DEPTH_TEST);
+                    gl.stencil_func(glow::EQUAL, 1, 1);
+                    gl.stencil_op(glow::KEEP, glow::KEEP, glow::KEEP);
+                    gl.uniform_matrix_4_f32_slice(
+                        mv_mat_loc.as_ref(),
+                        false,
+                        &cap_matrix(*plane, model.corners(*mv_mat)).to_cols_array(),
+                    );
+                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.quad_buffer));
+                    gl.vertex_attrib_pointer_f32(vertex_position_loc, 3, glow::FLOAT, false, 0, 0);
+                    gl.draw_arrays(glow::TRIANGLES, 0, 6);
+                }
+            }
+            gl.disable(glow::STENCIL_TEST);
+            gl.stencil_mask(!0);
+            gl.bind_vertex_array(None);
+        }
+    }
+}
+
+/// Places the -1..1 quad on `plane`, big enough to cover the box with
+/// `corners`.
+fn cap_matrix(plane: Vec4, corners: impl Iterator<Item = Vec3>) -> Mat4 {
+    let normal = plane.truncate();
+    let corners: Vec<_> = corners.collect();
+    let center = corners.iter().sum::<Vec3>() / corners.len().max(1) as f32;
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0., f32::max)
+        .max(f32::EPSILON);
+    // The center of the box, moved onto the plane.
+    let center = center - normal * (normal.dot(center) + plane.w);
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+    Mat4::from_cols(
+        (u * radius).extend(0.),
+        (v * radius).extend(0.),
+        normal.extend(0.),
+        center.extend(1.),
+    )
+}
@@ -0,0 +1,413 @@
+use eframe::glow;
+use egui::{Color32, DragValue, RichText, Slider, Stroke};
+use glam::{vec3, Mat4, Vec3, Vec4};
+use glow::HasContext as _;
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
+
+use super::{
+    picking::{paint_box_edges, PaneView},
+    DemoApp,
+};
+
+/// How many planes the scene, or one object, can have.
+pub(super) const MAX_PLANES_PER_LIST: usize = 6;
+/// How many planes can cut one object: the scene's, the object's own and
+/// its clip box.
+pub(super) const MAX_CLIPPING_PLANES: usize = MAX_PLANES_PER_LIST * 3;
+/// Half the side of the quad a clipping plane is drawn as.
+const PLANE_GIZMO_SIZE: f32 = 10.;
+const PLANE_GIZMO_COLOR: Color32 = Color32::from_rgb(80, 200, 230);
+
+/// `clipping.glsl` with its array size filled in, for fragment shaders that
+/// call `clipped`.
+pub(super) fn shader_source() -> String {
+    format!(
+        "#define MAX_CLIPPING_PLANES {MAX_CLIPPING_PLANES}\n{}",
+        include_str!("../clipping.glsl")
+    )
+}
+
+/// Uploads an object's plane equations from [`ObjectClipping::equations`]
+/// to the program in use.
+#[allow(unsafe_code)] // we need unsafe code to use glow
+pub(super) fn set_uniforms(gl: &glow::Context, program: glow::Program, planes: &[Vec4]) {
+    let planes = &planes[..planes.len().min(MAX_CLIPPING_PLANES)];
+    unsafe {
+        if !planes.is_empty() {
+            let data: Vec<f32> = planes.iter().flat_map(|plane| plane.to_array()).collect();
+            gl.uniform_4_f32_slice(
+                gl.get_uniform_location(program, "clipping_planes").as_ref(),
+                &data,
+            );
+        }
+        gl.uniform_1_i32(
+            gl.get_uniform_location(program, "clipping_plane_count")
+                .as_ref(),
+            planes.len() as i32,
+        );
+    }
+}
+
+/// Whether `point` survives all of the plane equations, like `clipped` in
+/// `clipping.glsl`.
+pub(super) fn keeps(planes: &[Vec4], point: Vec3) -> bool {
+    planes
+        .iter()
+        .all(|plane| plane.truncate().dot(point) + plane.w >= 0.)
+}
+
+/// Where clipping happens.
+#[derive(
+    Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize, Default, IntoStaticStr, EnumIter,
+)]
+pub(super) enum ClippingMode {
+    /// Fragments on the wrong side are discarded while drawing.
+    #[default]
+    #[strum(serialize = "Fragment Discard")]
+    Fragment,
+    /// Triangles are cut with Sutherland–Hodgman and the result uploaded as
+    /// a new mesh, see [`super::polygon_clipping`].
+    #[strum(serialize = "CPU (Sutherland–Hodgman)")]
+    Cpu,
+}
+
+/// The coordinate planes a clipping plane can be snapped to.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, IntoStaticStr, EnumIter)]
+enum PlanePreset {
+    #[strum(serialize = "X-Y")]
+    Xy,
+    #[strum(serialize = "Y-Z")]
+    Yz,
+    #[strum(serialize = "X-Z")]
+    Xz,
+}
+
+impl PlanePreset {
+    fn normal(self) -> Vec3 {
+        match self {
+            Self::Xy => Vec3::Z,
+            Self::Yz => Vec3::X,
+            Self::Xz => Vec3::Y,
+        }
+    }
+}
+
+/// Geometry behind the plane, the side `normal` points away from, is cut
+/// away.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct ClippingPlane {
+    pub(super) enabled: bool,
+    /// Keep the other side instead.
+    pub(super) invert: bool,
+    pub(super) pos: Vec3,
+    pub(super) normal: Vec3,
+}
+
+impl Default for ClippingPlane {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            invert: false,
+            pos: Vec3::ZERO,
+            normal: Vec3::Z,
+        }
+    }
+}
+
+impl ClippingPlane {
+    /// The plane as (normal, offset), `None` when it doesn't clip anything.
+    pub(super) fn equation(&self) -> Option<Vec4> {
+        let normal = self.normal.try_normalize()?;
+        let normal = if self.invert { -normal } else { normal };
+        self.enabled.then(|| normal.extend(-normal.dot(self.pos)))
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.enabled, "Enabled");
+            ui.checkbox(&mut self.invert, "Invert");
+        });
+        ui.add(Slider::new(&mut self.pos[0], -100.0..=100.0).text("Pos X"));
+        ui.add(Slider::new(&mut self.pos[1], -100.0..=100.0).text("Pos Y"));
+        ui.add(Slider::new(&mut self.pos[2], -100.0..=100.0).text("Pos Z"));
+        ui.add(Slider::new(&mut self.normal[0], -1.0..=1.0).text("Vec X"));
+        ui.add(Slider::new(&mut self.normal[1], -1.0..=1.0).text("Vec Y"));
+        ui.add(Slider::new(&mut self.normal[2], -1.0..=1.0).text("Vec Z"));
+        ui.horizontal(|ui| {
+            for preset in PlanePreset::iter() {
+                if ui.button(Into::<&'static str>::into(preset)).clicked() {
+                    // Keep the distance along the new normal.
+                    let normal = preset.normal();
+                    self.pos = normal * self.pos.dot(normal);
+                    self.normal = normal;
+                }
+            }
+        });
+    }
+}
+
+/// The enabled planes as equations, at most [`MAX_PLANES_PER_LIST`] of them.
+pub(super) fn equations(planes: &[ClippingPlane]) -> Vec<Vec4> {
+    planes
+        .iter()
+        .filter_map(ClippingPlane::equation)
+        .take(MAX_PLANES_PER_LIST)
+        .collect()
+}
+
+/// Moves a plane equation from model to world space with the object's
+/// model to world matrix.
+fn transform_equation(world: Mat4, equation: Vec4) -> Vec4 {
+    let equation = world.inverse().transpose() * equation;
+    equation / equation.truncate().length().max(f32::EPSILON)
+}
+
+/// Keeps only what is inside the box, in model space.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct ClipBox {
+    pub(super) enabled: bool,
+    pub(super) min: Vec3,
+    pub(super) max: Vec3,
+}
+
+impl Default for ClipBox {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min: Vec3::splat(-1.),
+            max: Vec3::ONE,
+        }
+    }
+}
+
+impl ClipBox {
+    /// The six faces facing inwards.
+    fn equations(&self) -> impl Iterator<Item = Vec4> + '_ {
+        [Vec3::X, Vec3::Y, Vec3::Z]
+            .into_iter()
+            .flat_map(|axis| {
+                [
+                    axis.extend(-axis.dot(self.min)),
+                    (-axis).extend(axis.dot(self.max)),
+                ]
+            })
+            .filter(|_| self.enabled)
+    }
+}
+
+/// How an object is clipped.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct ObjectClipping {
+    /// Leave the object whole whatever the scene's planes are.
+    pub(super) ignore_scene: bool,
+    /// In model space, so they move with the object.
+    pub(super) planes: Vec<ClippingPlane>,
+    pub(super) clip_box: ClipBox,
+}
+
+impl ObjectClipping {
+    /// Every plane that cuts the object in world space, given the scene's
+    /// `scene` and the object's model to world matrix.
+    pub(super) fn equations(&self, scene: &[Vec4], world: Mat4) -> Vec<Vec4> {
+        let scene = if self.ignore_scene { &[][..] } else { scene };
+        scene
+            .iter()
+            .copied()
+            .chain(
+                equations(&self.planes)
+                    .into_iter()
+                    .chain(self.clip_box.equations())
+                    .map(|equation| transform_equation(world, equation)),
+            )
+            .collect()
+    }
+
+    /// `bounds` is the model's bounding box, if it is known.
+    pub(super) fn ui(&mut self, ui: &mut egui::Ui, bounds: Option<[Vec3; 2]>) {
+        ui.checkbox(&mut self.ignore_scene, "Ignore the scene's planes");
+        ui.label("The planes and box below are in model space and move with the object.");
+
+        if ui
+            .add_enabled(
+                self.planes.len() < MAX_PLANES_PER_LIST,
+                egui::Button::new("Add Local Plane"),
+            )
+            .clicked()
+        {
+            self.planes.push(Default::default());
+        }
+        let mut removed = None;
+        for (id, plane) in self.planes.iter_mut().enumerate() {
+            egui::CollapsingHeader::new(format!("Local Plane {id}")).show(ui, |ui| {
+                plane.ui(ui);
+                if ui
+                    .button(RichText::new("Remove Plane").color(Color32::RED))
+                    .clicked()
+                {
+                    removed = Some(id);
+                }
+            });
+        }
+        if let Some(id) = removed {
+            self.planes.remove(id);
+        }
+
+        let clip_box = &mut self.clip_box;
+        ui.checkbox(&mut clip_box.enabled, "Clip Box");
+        for (name, corner) in [("Min", &mut clip_box.min), ("Max", &mut clip_box.max)] {
+            ui.horizontal(|ui| {
+                ui.label(name);
+                for value in corner.as_mut() {
+                    ui.add(DragValue::new(value).speed(0.05));
+                }
+            });
+        }
+        clip_box.max = clip_box.max.max(clip_box.min);
+        if ui
+            .add_enabled(bounds.is_some(), egui::Button::new("Fit Box to Bounds"))
+            .clicked()
+        {
+            if let Some([min, max]) = bounds {
+                clip_box.min = min;
+                clip_box.max = max;
+            }
+        }
+    }
+}
+
+impl DemoApp {
+    pub(super) fn clipping_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    self.clipping_planes.len() < MAX_PLANES_PER_LIST,
+                    egui::Button::new("Add Plane"),
+                )
+                .clicked()
+            {
+                self.clipping_planes.push(Default::default());
+            }
+            ui.checkbox(&mut self.show_clipping_planes, "Show Planes");
+        });
+        egui::ComboBox::new("clipping_mode", "Mode")
+            .selected_text(Into::<&'static str>::into(self.clipping_mode))
+            .show_ui(ui, |ui| {
+                for mode in ClippingMode::iter() {
+                    ui.selectable_value(
+                        &mut self.clipping_mode,
+                        mode,
+                        Into::<&'static str>::into(mode),
+                    );
+                }
+            });
+        ui.add_enabled(
+            self.clipping_mode == ClippingMode::Cpu,
+            egui::Checkbox::new(&mut self.highlight_clipped_edges, "Highlight Clipped Edges"),
+        );
+
+        let mut removed = None;
+        for (id, plane) in self.clipping_planes.iter_mut().enumerate() {
+            egui::CollapsingHeader::new(format!("Plane {id}")).show(ui, |ui| {
+                plane.ui(ui);
+                if ui
+                    .button(RichText::new("Remove Plane").color(Color32::RED))
+                    .clicked()
+                {
+                    removed = Some(id);
+                }
+            });
+        }
+        if let Some(id) = removed {
+            self.clipping_planes.remove(id);
+        }
+    }
+
+    /// Draws the enabled clipping planes over the pane `pane` as quads with
+    /// an arrow toward the side that is kept.
+    pub(super) fn paint_clipping_planes(&self, ui: &egui::Ui, pane: usize, rect: egui::Rect) {
+        if !self.show_clipping_planes {
+            return;
+        }
+        let Some(view) = self.pane_view(pane, rect) else {
+            return;
+        };
+        let painter = ui.painter().with_clip_rect(rect);
+        for plane in &self.clipping_planes {
+            if let Some(equation) = plane.equation() {
+                paint_plane(&painter, &view, plane.pos, equation.truncate(), 1.);
+            }
+        }
+
+        // The selected object's own planes and box, where they are now.
+        let Some(id) = self.selected_object.filter(|&id| id < self.objects.len()) else {
+            return;
+        };
+        let obj = &self.objects[id];
+        let world = super::scene::world_matrices(&self.objects)[id];
+        for plane in &obj.clipping.planes {
+            if let Some(equation) = plane.equation() {
+                let normal = transform_equation(world, equation).truncate();
+                let size = world.transform_vector3(Vec3::ONE).length() / 3f32.sqrt();
+                paint_plane(
+                    &painter,
+                    &view,
+                    world.transform_point3(plane.pos),
+                    normal,
+                    size,
+                );
+            }
+        }
+        let clip_box = &obj.clipping.clip_box;
+        if clip_box.enabled {
+            let corners = std::array::from_fn(|i| {
+                world.transform_point3(Vec3::select(
+                    glam::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                    clip_box.max,
+                    clip_box.min,
+                ))
+            });
+            paint_box_edges(
+                &painter,
+                &view,
+                corners,
+                Stroke::new(1.5, PLANE_GIZMO_COLOR),
+            );
+        }
+    }
+}
+
+/// Draws a plane as a quad, `scale` times the usual size, with an arrow
+/// toward the side that is kept.
+fn paint_plane(painter: &egui::Painter, view: &PaneView, pos: Vec3, normal: Vec3, scale: f32) {
+    let size = PLANE_GIZMO_SIZE * scale;
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+    let corners: Option<Vec<_>> = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)]
+        .into_iter()
+        .map(|(a, b)| view.to_screen(pos + (u * a + v * b) * size))
+        .collect();
+    if let Some(corners) = corners {
+        painter.add(egui::Shape::convex_polygon(
+            corners,
+            PLANE_GIZMO_COLOR.gamma_multiply(0.15),
+            Stroke::new(1.5, PLANE_GIZMO_COLOR),
+        ));
+    }
+    let tip = pos + normal * size * 0.5;
+    if let (Some(from), Some(to)) = (view.to_screen(pos), view.to_screen(tip)) {
+        painter.arrow(from, to - from, Stroke::new(1.5, PLANE_GIZMO_COLOR));
+    }
+}
+
+/// The plane clipping used to be limited to, far behind the origin so it
+/// doesn't cut anything at first.
+pub(super) fn default_planes() -> Vec<ClippingPlane> {
+    vec![ClippingPlane {
+        pos: vec3(0., 0., -100.),
+        ..Default::default()
+    }]
+}
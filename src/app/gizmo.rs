@@ -0,0 +1,546 @@
+use egui::{Color32, Pos2, Rect, Stroke};
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
+
+use super::{
+    picking::{PaneView, Ray},
+    scene, CGObject, DemoApp,
+};
+
+/// Length of the gizmo axes on screen.
+const GIZMO_PIXELS: f32 = 90.;
+/// How close the pointer has to be to a handle to grab it.
+const GRAB_PIXELS: f32 = 8.;
+const AXIS_COLORS: [Color32; 3] = [
+    Color32::from_rgb(230, 70, 70),
+    Color32::from_rgb(90, 200, 90),
+    Color32::from_rgb(70, 120, 240),
+];
+const ACTIVE_COLOR: Color32 = Color32::from_rgb(255, 210, 60);
+
+/// What dragging the gizmo does.
+#[derive(
+    Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize, Default, IntoStaticStr, EnumIter,
+)]
+pub(super) enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// Which axes translate and rotate along, scaling always uses the object's
+/// own.
+#[derive(
+    Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize, Default, IntoStaticStr, EnumIter,
+)]
+pub(super) enum GizmoSpace {
+    #[default]
+    World,
+    Local,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Snapping {
+    pub(super) enabled: bool,
+    pub(super) translate: f32,
+    /// In degrees.
+    pub(super) rotate: f32,
+    pub(super) scale: f32,
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            translate: 0.5,
+            rotate: 15.,
+            scale: 0.1,
+        }
+    }
+}
+
+fn snap(value: f32, increment: f32, enabled: bool) -> f32 {
+    if enabled && increment > 0. {
+        (value / increment).round() * increment
+    } else {
+        value
+    }
+}
+
+/// A part of the gizmo that can be dragged. What `Axis` does depends on the
+/// mode, the planes only exist for translation and the rings for rotation.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Handle {
+    Axis(usize),
+    /// The plane the axis is the normal of.
+    Plane(usize),
+    Ring(usize),
+    /// Uniform scale.
+    Center,
+}
+
+enum Shape {
+    Line(Vec<Vec3>),
+    Quad([Vec3; 4]),
+    Knob { at: Vec3, square: bool },
+}
+
+/// Where the gizmo sits, in world space.
+#[derive(Copy, Clone, Debug)]
+struct Frame {
+    origin: Vec3,
+    axes: [Vec3; 3],
+    /// The parent's model to world matrix.
+    parent: Mat4,
+}
+
+struct GizmoDrag {
+    handle: Handle,
+    pane: usize,
+    object: usize,
+    /// The object as it was when the drag started.
+    start: CGObject,
+    frame: Frame,
+    from: Pos2,
+    /// Rotation so far in degrees and the last screen angle, for rings.
+    angle: f32,
+    last_angle: f32,
+}
+
+/// The translate, rotate and scale manipulators of the selected object.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Gizmo {
+    pub(super) mode: GizmoMode,
+    pub(super) space: GizmoSpace,
+    pub(super) snapping: Snapping,
+    #[serde(skip)]
+    drag: Option<GizmoDrag>,
+}
+
+impl Gizmo {
+    pub(super) fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for mode in GizmoMode::iter() {
+                ui.selectable_value(&mut self.mode, mode, Into::<&'static str>::into(mode));
+            }
+        });
+        ui.horizontal(|ui| {
+            for space in GizmoSpace::iter() {
+                ui.selectable_value(&mut self.space, space, Into::<&'static str>::into(space));
+            }
+        });
+        ui.checkbox(&mut self.snapping.enabled, "Snapping");
+        ui.add(
+            egui::DragValue::new(&mut self.snapping.translate)
+                .speed(0.05)
+                .range(0.01..=10.)
+                .prefix("Move: "),
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.snapping.rotate)
+                .speed(0.5)
+                .range(1.0..=90.)
+                .prefix("Rotate: ")
+                .suffix("°"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.snapping.scale)
+                .speed(0.01)
+                .range(0.01..=1.)
+                .prefix("Scale: "),
+        );
+        ui.label("G, R and T switch between moving, rotating and scaling, L toggles local axes.");
+        ui.label("Hold Ctrl while dragging to flip snapping, Esc cancels.");
+    }
+
+    /// G, R and T pick the mode (S already flies backwards), L switches
+    /// between world and local axes.
+    pub(super) fn hotkeys(&mut self, ctx: &egui::Context) {
+        use egui::Key;
+        ctx.input(|i| {
+            for (key, mode) in [
+                (Key::G, GizmoMode::Translate),
+                (Key::R, GizmoMode::Rotate),
+                (Key::T, GizmoMode::Scale),
+            ] {
+                if i.key_pressed(key) {
+                    self.mode = mode;
+                }
+            }
+            if i.key_pressed(Key::L) {
+                self.space = match self.space {
+                    GizmoSpace::World => GizmoSpace::Local,
+                    GizmoSpace::Local => GizmoSpace::World,
+                };
+            }
+        });
+    }
+
+    fn frame(&self, objects: &[CGObject], id: usize) -> Frame {
+        let world = scene::world_matrices(objects);
+        let obj = &objects[id];
+        let parent = obj.parent.map_or(Mat4::IDENTITY, |parent| world[parent]);
+        let axes = if self.space == GizmoSpace::World && self.mode != GizmoMode::Scale {
+            [Vec3::X, Vec3::Y, Vec3::Z]
+        } else {
+            let rotation = obj.rotation_quat();
+            [Vec3::X, Vec3::Y, Vec3::Z]
+                .map(|axis| parent.transform_vector3(rotation * axis).normalize_or(axis))
+        };
+        Frame {
+            origin: world[id].transform_point3(obj.pivot),
+            axes,
+            parent,
+        }
+    }
+
+    fn shapes(&self, frame: &Frame, size: f32) -> Vec<(Handle, Vec<Shape>)> {
+        let Frame { origin, axes, .. } = *frame;
+        let mut shapes = Vec::new();
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let square = self.mode == GizmoMode::Scale;
+                for (i, axis) in axes.into_iter().enumerate() {
+                    let tip = origin + axis * size;
+                    shapes.push((
+                        Handle::Axis(i),
+                        vec![
+                            Shape::Line(vec![origin + axis * size * 0.2, tip]),
+                            Shape::Knob { at: tip, square },
+                        ],
+                    ));
+                }
+                if square {
+                    shapes.push((Handle::Center, vec![Shape::Knob { at: origin, square }]));
+                } else {
+                    for i in 0..3 {
+                        let (u, v) = (axes[(i + 1) % 3] * size, axes[(i + 2) % 3] * size);
+                        let corner = origin + (u + v) * 0.25;
+                        shapes.push((
+                            Handle::Plane(i),
+                            vec![Shape::Quad([
+                                corner,
+                                corner + u * 0.2,
+                                corner + (u + v) * 0.2,
+                                corner + v * 0.2,
+                            ])],
+                        ));
+                    }
+                }
+            }
+            GizmoMode::Rotate => {
+                for i in 0..3 {
+                    let (u, v) = (axes[(i + 1) % 3] * size, axes[(i + 2) % 3] * size);
+                    let ring = (0..=48)
+                        .map(|step| {
+                            let angle = step as f32 / 48. * std::f32::consts::TAU;
+                            origin + u * angle.cos() + v * angle.sin()
+                        })
+                        .collect();
+                    shapes.push((Handle::Ring(i), vec![Shape::Line(ring)]));
+                }
+            }
+        }
+        shapes
+    }
+
+    /// The handle under `pos`, if any.
+    fn hovered(&self, view: &PaneView, frame: &Frame, pos: Pos2) -> Option<Handle> {
+        let size = view.units_per_pixel(frame.origin) * GIZMO_PIXELS;
+        self.shapes(frame, size)
+            .into_iter()
+            .filter_map(|(handle, shapes)| {
+                shapes
+                    .iter()
+                    .filter_map(|shape| screen_distance(view, shape, pos))
+                    .min_by(f32::total_cmp)
+                    .map(|distance| (handle, distance))
+            })
+            .filter(|&(_, distance)| distance <= GRAB_PIXELS)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(handle, _)| handle)
+    }
+
+    fn paint(
+        &self,
+        painter: &egui::Painter,
+        view: &PaneView,
+        frame: &Frame,
+        active: Option<Handle>,
+    ) {
+        let size = view.units_per_pixel(frame.origin) * GIZMO_PIXELS;
+        for (handle, shapes) in self.shapes(frame, size) {
+            let color = if Some(handle) == active {
+                ACTIVE_COLOR
+            } else {
+                match handle {
+                    Handle::Axis(i) | Handle::Plane(i) | Handle::Ring(i) => AXIS_COLORS[i],
+                    Handle::Center => Color32::LIGHT_GRAY,
+                }
+            };
+            for shape in shapes {
+                match shape {
+                    Shape::Line(points) => {
+                        let points: Option<Vec<_>> =
+                            points.into_iter().map(|p| view.to_screen(p)).collect();
+                        if let Some(points) = points {
+                            painter.add(egui::Shape::line(points, Stroke::new(2.5, color)));
+                        }
+                    }
+                    Shape::Quad(corners) => {
+                        let points: Option<Vec<_>> =
+                            corners.into_iter().map(|p| view.to_screen(p)).collect();
+                        if let Some(points) = points {
+                            painter.add(egui::Shape::convex_polygon(
+                                points,
+                                color.gamma_multiply(0.4),
+                                Stroke::new(1.5, color),
+                            ));
+                        }
+                    }
+                    Shape::Knob { at, square } => {
+                        if let Some(at) = view.to_screen(at) {
+                            if square {
+                                painter.rect_filled(
+                                    Rect::from_center_size(at, egui::vec2(10., 10.)),
+                                    0.,
+                                    color,
+                                );
+                            } else {
+                                painter.circle_filled(at, 5., color);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Pixels between `pos` and a shape on screen.
+fn screen_distance(view: &PaneView, shape: &Shape, pos: Pos2) -> Option<f32> {
+    match shape {
+        Shape::Line(points) => {
+            let points: Option<Vec<_>> = points.iter().map(|&p| view.to_screen(p)).collect();
+            points?
+                .windows(2)
+                .map(|segment| segment_distance(segment[0], segment[1], pos))
+                .min_by(f32::total_cmp)
+        }
+        Shape::Quad(corners) => {
+            let points: Option<Vec<_>> = corners.iter().map(|&p| view.to_screen(p)).collect();
+            let points = points?;
+            // Inside if the pointer is on the same side of every edge.
+            let sides: Vec<f32> = (0..4)
+                .map(|i| {
+                    (points[(i + 1) % 4] - points[i])
+                        .rot90()
+                        .dot(pos - points[i])
+                })
+                .collect();
+            if sides.iter().all(|&side| side >= 0.) || sides.iter().all(|&side| side <= 0.) {
+                Some(0.)
+            } else {
+                None
+            }
+        }
+        Shape::Knob { at, .. } => Some((view.to_screen(*at)? - pos).length() - 5.),
+    }
+}
+
+fn segment_distance(a: Pos2, b: Pos2, pos: Pos2) -> f32 {
+    let ab = b - a;
+    let t = ((pos - a).dot(ab) / ab.length_sq().max(f32::EPSILON)).clamp(0., 1.);
+    (a + ab * t - pos).length()
+}
+
+/// How far along the line `origin + s * axis` the point closest to `ray` is.
+fn axis_param(ray: Ray, origin: Vec3, axis: Vec3) -> Option<f32> {
+    let w = origin - ray.origin;
+    let (a, b, c) = (axis.dot(axis), axis.dot(ray.dir), ray.dir.dot(ray.dir));
+    let (d, e) = (axis.dot(w), ray.dir.dot(w));
+    let denominator = a * c - b * b;
+    (denominator.abs() > 1e-6 * a * c).then(|| (b * e - c * d) / denominator)
+}
+
+/// Where `ray` crosses the plane through `origin` with `normal`.
+fn plane_point(ray: Ray, origin: Vec3, normal: Vec3) -> Option<Vec3> {
+    let facing = ray.dir.dot(normal);
+    (facing.abs() > 1e-6).then(|| ray.at((origin - ray.origin).dot(normal) / facing))
+}
+
+/// The screen angle of `pos` around `center` in degrees, counterclockwise.
+fn screen_angle(center: Pos2, pos: Pos2) -> f32 {
+    (center.y - pos.y).atan2(pos.x - center.x).to_degrees()
+}
+
+impl GizmoDrag {
+    /// The selected object as this drag turns it with the pointer at `pos`.
+    fn apply(
+        &mut self,
+        mode: GizmoMode,
+        snapping: Snapping,
+        view: &PaneView,
+        pos: Pos2,
+    ) -> Option<CGObject> {
+        let Frame {
+            origin,
+            axes,
+            parent,
+        } = self.frame;
+        let (from, to) = (view.ray(self.from), view.ray(pos));
+        let mut obj = self.start.clone();
+        match (mode, self.handle) {
+            (GizmoMode::Translate, Handle::Axis(i)) => {
+                let moved = axis_param(to, origin, axes[i])? - axis_param(from, origin, axes[i])?;
+                let delta = axes[i] * snap(moved, snapping.translate, snapping.enabled);
+                obj.translation += parent.inverse().transform_vector3(delta);
+            }
+            (GizmoMode::Translate, Handle::Plane(i)) => {
+                let moved = plane_point(to, origin, axes[i])? - plane_point(from, origin, axes[i])?;
+                let delta: Vec3 = [axes[(i + 1) % 3], axes[(i + 2) % 3]]
+                    .into_iter()
+                    .map(|axis| axis * snap(moved.dot(axis), snapping.translate, snapping.enabled))
+                    .sum();
+                obj.translation += parent.inverse().transform_vector3(delta);
+            }
+            (GizmoMode::Rotate, Handle::Ring(i)) => {
+                let center = view.to_screen(origin)?;
+                let angle = screen_angle(center, pos);
+                // Unwrapped, so going around more than once keeps adding up.
+                self.angle += (angle - self.last_angle + 540.).rem_euclid(360.) - 180.;
+                self.last_angle = angle;
+                let sign = view.toward_eye(origin).dot(axes[i]).signum();
+                let angle = snap(self.angle, snapping.rotate, snapping.enabled) * sign;
+                let axis = parent
+                    .inverse()
+                    .transform_vector3(axes[i])
+                    .normalize_or(axes[i]);
+                obj.set_rotation_quat(
+                    Quat::from_axis_angle(axis, angle.to_radians()) * self.start.rotation_quat(),
+                );
+            }
+            (GizmoMode::Scale, Handle::Axis(i)) => {
+                let start = axis_param(from, origin, axes[i])?;
+                if start.abs() < f32::EPSILON {
+                    return None;
+                }
+                let factor = axis_param(to, origin, axes[i])? / start;
+                obj.scale[i] *= 1. + snap(factor - 1., snapping.scale, snapping.enabled);
+            }
+            (GizmoMode::Scale, Handle::Center) => {
+                let center = view.to_screen(origin)?;
+                let start = (self.from - center).length();
+                if start < 1. {
+                    return None;
+                }
+                let factor = (pos - center).length() / start;
+                obj.scale *= 1. + snap(factor - 1., snapping.scale, snapping.enabled);
+            }
+            _ => return None,
+        }
+        Some(obj)
+    }
+}
+
+impl DemoApp {
+    /// Drags the gizmo of the selected object in the pane `pane`. Returns
+    /// whether the gizmo took the pointer, in which case the camera and
+    /// picking should leave it alone.
+    pub(super) fn gizmo_controls(
+        &mut self,
+        ui: &egui::Ui,
+        pane: usize,
+        rect: Rect,
+        response: &egui::Response,
+    ) -> bool {
+        let Some(id) = self.selected_object.filter(|&id| id < self.objects.len()) else {
+            self.gizmo.drag = None;
+            return false;
+        };
+        let Some(view) = self.pane_view(pane, rect) else {
+            return false;
+        };
+
+        if let Some(drag) = &mut self.gizmo.drag {
+            if drag.pane != pane {
+                return false;
+            }
+            let (escape, flip_snapping) =
+                ui.input(|i| (i.key_pressed(egui::Key::Escape), i.modifiers.command));
+            if drag.object != id {
+                self.gizmo.drag = None;
+                return false;
+            }
+            if escape {
+                self.objects[id] = drag.start.clone();
+                self.gizmo.drag = None;
+                return true;
+            }
+            if response.dragged_by(egui::PointerButton::Primary) {
+                let mut snapping = self.gizmo.snapping;
+                snapping.enabled ^= flip_snapping;
+                if let Some(pos) = response.interact_pointer_pos() {
+                    if let Some(obj) = drag.apply(self.gizmo.mode, snapping, &view, pos) {
+                        self.objects[id] = obj;
+                    }
+                }
+            } else {
+                self.gizmo.drag = None;
+            }
+            return true;
+        }
+
+        let frame = self.gizmo.frame(&self.objects, id);
+        let press = ui.input(|i| i.pointer.press_origin());
+        if response.drag_started_by(egui::PointerButton::Primary) {
+            if let Some(from) = press {
+                if let Some(handle) = self.gizmo.hovered(&view, &frame, from) {
+                    let last_angle = view
+                        .to_screen(frame.origin)
+                        .map_or(0., |center| screen_angle(center, from));
+                    self.gizmo.drag = Some(GizmoDrag {
+                        handle,
+                        pane,
+                        object: id,
+                        start: self.objects[id].clone(),
+                        frame,
+                        from,
+                        angle: 0.,
+                        last_angle,
+                    });
+                    return true;
+                }
+            }
+        }
+        // Clicking a handle shouldn't select what is behind it.
+        response.clicked()
+            && response
+                .interact_pointer_pos()
+                .is_some_and(|pos| self.gizmo.hovered(&view, &frame, pos).is_some())
+    }
+
+    /// Draws the gizmo of the selected object over the pane `pane`.
+    pub(super) fn paint_gizmo(&self, ui: &egui::Ui, pane: usize, rect: Rect) {
+        let Some(id) = self.selected_object.filter(|&id| id < self.objects.len()) else {
+            return;
+        };
+        let Some(view) = self.pane_view(pane, rect) else {
+            return;
+        };
+        let frame = self.gizmo.frame(&self.objects, id);
+        let active = match &self.gizmo.drag {
+            Some(drag) => Some(drag.handle),
+            None => ui
+                .input(|i| i.pointer.hover_pos())
+                .filter(|pos| rect.contains(*pos))
+                .and_then(|pos| self.gizmo.hovered(&view, &frame, pos)),
+        };
+        self.gizmo
+            .paint(&ui.painter().with_clip_rect(rect), &view, &frame, active);
+    }
+}
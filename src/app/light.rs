@@ -0,0 +1,333 @@
+use bytemuck::{Pod, Zeroable};
+use egui::Slider;
+use glam::{vec3, Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
+
+use super::{obj::generate_normals, ICGJson};
+
+/// How many lights the `Lights` uniform block has room for, passed to the
+/// shaders as `MAX_LIGHTS`.
+pub(super) const MAX_LIGHTS: usize = 16;
+
+/// Binding point of the `Lights` uniform block.
+pub(super) const LIGHTS_BINDING: u32 = 0;
+
+/// How many lights of each kind can cast shadows: point lights get a cube
+/// map, the others a 2D shadow map. Lights past that simply don't cast any.
+pub(super) const MAX_SHADOW_MAPS: usize = 4;
+
+/// Near and far planes of every shadow map projection.
+pub(super) const SHADOW_NEAR: f32 = 0.5;
+pub(super) const SHADOW_FAR: f32 = 100.;
+
+/// Half the size of the box around the origin that directional lights cast
+/// shadows in.
+const DIRECTIONAL_SHADOW_EXTENT: f32 = 40.;
+
+pub(super) const SHADOW_RESOLUTIONS: [u32; 5] = [256, 512, 1024, 2048, 4096];
+
+/// Matches the light type codes in `lighting.glsl`.
+#[derive(Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Default, IntoStaticStr, EnumIter)]
+pub(super) enum LightKind {
+    Directional = 0,
+    #[default]
+    Point,
+    Spot,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Light {
+    pub(super) kind: LightKind,
+    /// Where the light is, directional lights only use it to place their gizmo.
+    pub(super) position: Vec3,
+    /// Where directional and spot lights point to.
+    pub(super) direction: Vec3,
+    pub(super) color: [f32; 3],
+    /// Constant, linear and quadratic attenuation of point and spot lights.
+    pub(super) attenuation: Vec3,
+    /// Spot lights are at full strength inside this angle from `direction`, in degrees.
+    pub(super) inner_angle: f32,
+    /// And fade out to nothing at this angle, in degrees.
+    pub(super) outer_angle: f32,
+    pub(super) cast_shadows: bool,
+    /// How far shadow lookups are moved towards the light, against shadow acne.
+    pub(super) shadow_bias: f32,
+    /// Width and height of the shadow map (of each cube face for point lights).
+    pub(super) shadow_resolution: u32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            kind: Default::default(),
+            position: vec3(0., 5., 5.),
+            direction: vec3(0., -1., 0.),
+            color: [1., 1., 1.],
+            attenuation: vec3(1., 0., 0.),
+            inner_angle: 20.,
+            outer_angle: 30.,
+            cast_shadows: false,
+            shadow_bias: 0.05,
+            shadow_resolution: 1024,
+        }
+    }
+}
+
+impl Light {
+    /// The three lights the scene always used to have.
+    pub(super) fn default_lights() -> Vec<Self> {
+        [
+            (vec3(0., 5., 5.), [1., 1., 0.]),
+            (vec3(17., 5., -2.), [1., 0., 1.]),
+            (vec3(-17., 5., -2.), [0., 1., 1.]),
+        ]
+        .into_iter()
+        .map(|(position, color)| Self {
+            position,
+            color,
+            ..Default::default()
+        })
+        .collect()
+    }
+
+    pub(super) fn ui(&mut self, ui: &mut egui::Ui, id: usize) {
+        egui::ComboBox::new(("light_kind", id), "Type")
+            .selected_text(Into::<&'static str>::into(self.kind))
+            .show_ui(ui, |ui| {
+                for kind in LightKind::iter() {
+                    ui.selectable_value(&mut self.kind, kind, Into::<&'static str>::into(kind));
+                }
+            });
+        ui.add(Slider::new(&mut self.position[0], -20.0..=20.0).text("Position.x"));
+        ui.add(Slider::new(&mut self.position[1], -20.0..=20.0).text("Position.y"));
+        ui.add(Slider::new(&mut self.position[2], -20.0..=20.0).text("Position.z"));
+        if self.kind != LightKind::Point {
+            ui.add(Slider::new(&mut self.direction[0], -1.0..=1.0).text("Direction.x"));
+            ui.add(Slider::new(&mut self.direction[1], -1.0..=1.0).text("Direction.y"));
+            ui.add(Slider::new(&mut self.direction[2], -1.0..=1.0).text("Direction.z"));
+        }
+        if self.kind != LightKind::Directional {
+            ui.add(Slider::new(&mut self.attenuation[0], 0.0..=2.0).text("Constant"));
+            ui.add(Slider::new(&mut self.attenuation[1], 0.0..=1.0).text("Linear"));
+            ui.add(Slider::new(&mut self.attenuation[2], 0.0..=0.1).text("Quadratic"));
+        }
+        if self.kind == LightKind::Spot {
+            ui.add(Slider::new(&mut self.inner_angle, 0.0..=90.0).text("Inner angle"));
+            ui.add(Slider::new(&mut self.outer_angle, 0.0..=90.0).text("Outer angle"));
+            self.inner_angle = self.inner_angle.min(self.outer_angle);
+        }
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgb(&mut self.color);
+            ui.label("Color");
+        });
+        ui.checkbox(&mut self.cast_shadows, "Cast shadows");
+        ui.add_enabled_ui(self.cast_shadows, |ui| {
+            ui.add(
+                Slider::new(&mut self.shadow_bias, 0.0..=1.0)
+                    .logarithmic(true)
+                    .text("Shadow bias"),
+            );
+            egui::ComboBox::new(("light_shadow_resolution", id), "Shadow resolution")
+                .selected_text(format!("{}", self.shadow_resolution))
+                .show_ui(ui, |ui| {
+                    for resolution in SHADOW_RESOLUTIONS {
+                        ui.selectable_value(
+                            &mut self.shadow_resolution,
+                            resolution,
+                            format!("{resolution}"),
+                        );
+                    }
+                });
+        });
+    }
+
+    /// World to clip space of the light, for lights with a 2D shadow map.
+    pub(super) fn shadow_matrix(&self) -> Mat4 {
+        let direction = self.direction.normalize_or(Vec3::NEG_Y);
+        let up = if direction.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        if self.kind == LightKind::Directional {
+            let extent = DIRECTIONAL_SHADOW_EXTENT;
+            let eye = -direction * (SHADOW_FAR + SHADOW_NEAR) / 2.;
+            Mat4::orthographic_rh_gl(-extent, extent, -extent, extent, SHADOW_NEAR, SHADOW_FAR)
+                * Mat4::look_to_rh(eye, direction, up)
+        } else {
+            let fov = (self.outer_angle * 2.).clamp(1., 170.).to_radians();
+            Mat4::perspective_rh_gl(fov, 1., SHADOW_NEAR, SHADOW_FAR)
+                * Mat4::look_to_rh(self.position, direction, up)
+        }
+    }
+
+    /// World to clip space of each cube map face, in the usual +X, -X, +Y,
+    /// -Y, +Z, -Z order, for point lights.
+    pub(super) fn cube_shadow_matrices(&self) -> [Mat4; 6] {
+        let projection = Mat4::perspective_rh_gl(90f32.to_radians(), 1., SHADOW_NEAR, SHADOW_FAR);
+        [
+            (Vec3::X, Vec3::NEG_Y),
+            (Vec3::NEG_X, Vec3::NEG_Y),
+            (Vec3::Y, Vec3::Z),
+            (Vec3::NEG_Y, Vec3::NEG_Z),
+            (Vec3::Z, Vec3::NEG_Y),
+            (Vec3::NEG_Z, Vec3::NEG_Y),
+        ]
+        .map(|(direction, up)| projection * Mat4::look_to_rh(self.position, direction, up))
+    }
+
+    /// Where to draw the gizmo, which for directional and spot lights is
+    /// modelled pointing towards -Z.
+    pub(super) fn gizmo_matrix(&self) -> Mat4 {
+        let rotation =
+            Quat::from_rotation_arc(Vec3::NEG_Z, self.direction.normalize_or(Vec3::NEG_Z));
+        match self.kind {
+            LightKind::Point => Mat4::from_translation(self.position),
+            LightKind::Directional => Mat4::from_rotation_translation(rotation, self.position),
+            LightKind::Spot => {
+                // Opening of the cone follows the outer angle, capped so it stays visible.
+                let radius = self.outer_angle.min(80.).to_radians().tan() * 2.;
+                Mat4::from_scale_rotation_translation(
+                    vec3(radius, radius, 2.),
+                    rotation,
+                    self.position,
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum ShadowSlot {
+    Map(usize),
+    Cube(usize),
+}
+
+/// Hands out the shadow maps to the lights that cast shadows, in order.
+pub(super) fn shadow_slots(lights: &[Light]) -> Vec<Option<ShadowSlot>> {
+    let (mut maps, mut cubes) = (0, 0);
+    lights
+        .iter()
+        .map(|light| {
+            if !light.cast_shadows {
+                None
+            } else if light.kind == LightKind::Point {
+                cubes += 1;
+                (cubes <= MAX_SHADOW_MAPS).then_some(ShadowSlot::Cube(cubes - 1))
+            } else {
+                maps += 1;
+                (maps <= MAX_SHADOW_MAPS).then_some(ShadowSlot::Map(maps - 1))
+            }
+        })
+        .collect()
+}
+
+/// Color of the gizmo meshes, matches `Light.json`.
+const GIZMO_COLOR: f32 = 0.5;
+
+fn gizmo_json(vertex_positions: Vec<f32>) -> ICGJson {
+    let indices: Vec<u32> = (0..vertex_positions.len() as u32 / 3).collect();
+    let colors = vec![GIZMO_COLOR; vertex_positions.len()];
+    ICGJson {
+        vertex_normals: generate_normals(&vertex_positions, &indices),
+        vertex_frontcolors: colors.clone(),
+        vertex_backcolors: colors,
+        vertex_positions,
+        vertex_texture_coords: Vec::new(),
+    }
+}
+
+/// Pushes a closed (truncated) cone along the Z axis, with a ring of `r0` at
+/// `z0` and a ring of `r1` at `z1`.
+fn push_frustum(positions: &mut Vec<f32>, (r0, z0): (f32, f32), (r1, z1): (f32, f32)) {
+    const SEGMENTS: usize = 16;
+    let ring = |i: usize, r: f32, z: f32| {
+        let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+        vec3(angle.cos() * r, angle.sin() * r, z)
+    };
+    let (c0, c1) = (vec3(0., 0., z0), vec3(0., 0., z1));
+    for i in 0..SEGMENTS {
+        let (a0, b0) = (ring(i, r0, z0), ring(i + 1, r0, z0));
+        let (a1, b1) = (ring(i, r1, z1), ring(i + 1, r1, z1));
+        for v in [a0, b0, b1, a0, b1, a1, c0, b0, a0, c1, a1, b1] {
+            positions.extend_from_slice(&v.to_array());
+        }
+    }
+}
+
+/// Spot light gizmo, a unit cone with its tip at the origin opening towards -Z.
+pub(super) fn spot_gizmo() -> ICGJson {
+    let mut positions = Vec::new();
+    push_frustum(&mut positions, (0., 0.), (1., -1.));
+    gizmo_json(positions)
+}
+
+/// Directional light gizmo, an arrow pointing towards -Z.
+pub(super) fn directional_gizmo() -> ICGJson {
+    let mut positions = Vec::new();
+    push_frustum(&mut positions, (0.15, 1.5), (0.15, -1.));
+    push_frustum(&mut positions, (0.5, -1.), (0., -2.));
+    gizmo_json(positions)
+}
+
+/// One entry of the `Lights` uniform block in `lighting.glsl`, std140 layout.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct LightStd140 {
+    location: [f32; 4],
+    color: [f32; 4],
+    direction: [f32; 4],
+    attenuation: [f32; 4],
+    cone: [f32; 4],
+    shadow: [f32; 4],
+    shadow_matrix: [f32; 16],
+}
+
+/// The `Lights` uniform block in `lighting.glsl`, std140 layout.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(super) struct LightBlock {
+    lights: [LightStd140; MAX_LIGHTS],
+    count: i32,
+    _padding: [i32; 3],
+}
+
+impl LightBlock {
+    /// Packs up to [`MAX_LIGHTS`] lights, the rest are ignored.
+    pub(super) fn new(lights: &[Light]) -> Self {
+        let mut block = Self::zeroed();
+        let shadow_slots = shadow_slots(lights);
+        for ((slot, light), shadow_slot) in block.lights.iter_mut().zip(lights).zip(shadow_slots) {
+            *slot = LightStd140 {
+                location: light.position.extend(light.kind as i32 as f32).to_array(),
+                color: [light.color[0], light.color[1], light.color[2], 1.],
+                direction: light
+                    .direction
+                    .normalize_or(Vec3::NEG_Y)
+                    .extend(0.)
+                    .to_array(),
+                attenuation: light.attenuation.extend(0.).to_array(),
+                cone: [
+                    light.inner_angle.to_radians().cos(),
+                    light.outer_angle.to_radians().cos(),
+                    0.,
+                    0.,
+                ],
+                shadow: [
+                    match shadow_slot {
+                        Some(ShadowSlot::Map(i) | ShadowSlot::Cube(i)) => i as f32,
+                        None => -1.,
+                    },
+                    light.shadow_bias,
+                    1. / light.shadow_resolution as f32,
+                    0.,
+                ],
+                shadow_matrix: light.shadow_matrix().to_cols_array(),
+            };
+        }
+        block.count = lights.len().min(MAX_LIGHTS) as i32;
+        block
+    }
+}
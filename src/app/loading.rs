@@ -0,0 +1,230 @@
+use std::fmt;
+
+use poll_promise::Promise;
+
+use super::{
+    obj,
+    validate::{RepairOptions, ValidationReport},
+    ICGJson,
+};
+
+/// Why a model couldn't be loaded, shown next to the model in the model list.
+#[derive(Clone, Debug)]
+pub(super) enum ModelLoadError {
+    /// The request never got a response.
+    Network(String),
+    /// The server responded, but not with a success status.
+    HttpStatus { status: u16, status_text: String },
+    /// A local file couldn't be read.
+    #[cfg(not(target_arch = "wasm32"))]
+    Io(String),
+    /// The data isn't a model we understand.
+    Parse {
+        message: String,
+        location: Option<(usize, usize)>,
+    },
+    /// The model parsed, but its arrays don't describe a valid mesh.
+    Validation(String),
+}
+
+impl fmt::Display for ModelLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(err) => write!(f, "network error: {err}"),
+            Self::HttpStatus {
+                status,
+                status_text,
+            } => write!(f, "HTTP {status} {status_text}"),
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Io(err) => write!(f, "cannot read file: {err}"),
+            Self::Parse {
+                message,
+                location: Some((line, column)),
+            } => write!(f, "parse error at line {line}, column {column}: {message}"),
+            Self::Parse {
+                message,
+                location: None,
+            } => write!(f, "parse error: {message}"),
+            Self::Validation(err) => write!(f, "invalid model: {err}"),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ModelLoadError {
+    fn from(err: serde_json::Error) -> Self {
+        // serde_json reports line 0 when it has no position.
+        let location = (err.line() != 0).then(|| (err.line(), err.column()));
+        let message = err.to_string();
+        // It also appends the position to its message, we show it ourselves.
+        let message = match location {
+            Some((line, column)) => message
+                .strip_suffix(&format!(" at line {line} column {column}"))
+                .map(str::to_string)
+                .unwrap_or(message),
+            None => message,
+        };
+        Self::Parse { message, location }
+    }
+}
+
+impl From<tobj::LoadError> for ModelLoadError {
+    fn from(err: tobj::LoadError) -> Self {
+        Self::Parse {
+            message: err.to_string(),
+            location: None,
+        }
+    }
+}
+
+type TextResult = Result<String, ModelLoadError>;
+
+/// A parsed model along with what validation found in it before repairing.
+pub(super) type LoadResult = Result<(ICGJson, ValidationReport), ModelLoadError>;
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+fn fetch_url(url: String, on_done: impl 'static + Send + FnOnce(TextResult)) {
+    ehttp::fetch(ehttp::Request::get(url), move |response| {
+        on_done(match response {
+            Err(err) => Err(ModelLoadError::Network(err)),
+            Ok(res) if !res.ok => Err(ModelLoadError::HttpStatus {
+                status: res.status,
+                status_text: res.status_text,
+            }),
+            Ok(res) => res.text().map(String::from).ok_or(ModelLoadError::Parse {
+                message: "response is not valid UTF-8".into(),
+                location: None,
+            }),
+        });
+    });
+}
+
+/// Reads `source` as text and hands it to `on_done`.
+///
+/// Absolute `http(s)://` sources are always fetched. Everything else is
+/// resolved against `asset_root`, which may be either a URL or a directory;
+/// `file://` sources and directory roots are read with `std::fs` on a
+/// background thread.
+#[cfg(not(target_arch = "wasm32"))]
+fn fetch_text(asset_root: &str, source: &str, on_done: impl 'static + Send + FnOnce(TextResult)) {
+    if is_url(source) {
+        fetch_url(source.to_string(), on_done);
+    } else if is_url(asset_root) {
+        fetch_url(format!("{}{}", asset_root, source), on_done);
+    } else {
+        let path = match source.strip_prefix("file://") {
+            Some(path) => std::path::PathBuf::from(path),
+            None => std::path::Path::new(asset_root).join(source.trim_start_matches('/')),
+        };
+        std::thread::spawn(move || {
+            on_done(
+                std::fs::read_to_string(&path)
+                    .map_err(|err| ModelLoadError::Io(format!("{}: {err}", path.display()))),
+            )
+        });
+    }
+}
+
+/// Reads `source` as text and hands it to `on_done`.
+///
+/// There is no filesystem on the web, relative sources are always fetched
+/// from `asset_root`.
+#[cfg(target_arch = "wasm32")]
+fn fetch_text(asset_root: &str, source: &str, on_done: impl 'static + Send + FnOnce(TextResult)) {
+    if is_url(source) {
+        fetch_url(source.to_string(), on_done);
+    } else {
+        fetch_url(format!("{}{}", asset_root, source), on_done);
+    }
+}
+
+/// Starts loading the model at `source`, picking the parser from its extension.
+pub(super) fn load_model_source(
+    asset_root: &str,
+    source: &str,
+    repair: RepairOptions,
+) -> Promise<LoadResult> {
+    let (tx, rx) = Promise::new();
+    if source.to_lowercase().ends_with(".obj") {
+        let root = asset_root.to_string();
+        let obj_source = source.to_string();
+        fetch_text(asset_root, source, move |obj| {
+            let obj = match obj {
+                Ok(obj) => obj,
+                Err(err) => {
+                    tx.send(Err(err));
+                    return;
+                }
+            };
+            let Some(mtllib) = obj::find_mtllib(&obj) else {
+                tx.send(ICGJson::from_obj(&obj, None).and_then(|json| json.checked(repair)));
+                return;
+            };
+            let mtl_source = obj::sibling_source(&obj_source, mtllib);
+            fetch_text(&root, &mtl_source, move |mtl| {
+                // The material file is optional, fall back to default colors.
+                let mtl = mtl.ok();
+                tx.send(
+                    ICGJson::from_obj(&obj, mtl.as_deref()).and_then(|json| json.checked(repair)),
+                );
+            });
+        });
+    } else {
+        fetch_text(asset_root, source, move |text| {
+            tx.send(text.and_then(|text| {
+                serde_json::from_str::<ICGJson>(&text)
+                    .map_err(ModelLoadError::from)
+                    .and_then(|json| json.checked(repair))
+            }));
+        });
+    }
+    rx
+}
+
+impl ICGJson {
+    /// Validates the model, repairs what `repair` allows and rejects it if it
+    /// still can't be drawn safely.
+    fn checked(mut self, repair: RepairOptions) -> LoadResult {
+        let report = self.validate();
+        if report.is_clean() {
+            return Ok((self, report));
+        }
+        self.repair(&report, repair);
+        let remaining = self.validate();
+        if remaining.is_fatal() {
+            return Err(ModelLoadError::Validation(remaining.to_string()));
+        }
+        Ok((self, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(json: &str) -> ModelLoadError {
+        serde_json::from_str::<ICGJson>(json).unwrap_err().into()
+    }
+
+    #[test]
+    fn json_error_location() {
+        let ModelLoadError::Parse { message, location } =
+            parse_error("{\n  \"vertexPositions\": [1,,]\n}")
+        else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(location, Some((2, 25)));
+        assert!(!message.contains("line"), "{message}");
+    }
+
+    #[test]
+    fn json_error_without_location() {
+        let err: ModelLoadError = <serde_json::Error as serde::de::Error>::custom("bad").into();
+        let ModelLoadError::Parse { message, location } = err else {
+            panic!("expected a parse error");
+        };
+        assert_eq!((message.as_str(), location), ("bad", None));
+    }
+}
@@ -0,0 +1,44 @@
+use egui::Slider;
+use serde::{Deserialize, Serialize};
+
+/// How an object reacts to light, sent to the shaders for every draw.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Material {
+    pub(super) ka: f32,
+    pub(super) kd: f32,
+    pub(super) ks: f32,
+    pub(super) shininess: f32,
+    /// Used instead of the model's vertex colors unless `use_vertex_colors` is set.
+    pub(super) base_color: [f32; 3],
+    pub(super) use_vertex_colors: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            ka: 1.,
+            kd: 0.6,
+            ks: 0.3,
+            shininess: 20.,
+            base_color: [0.8, 0.8, 0.8],
+            use_vertex_colors: true,
+        }
+    }
+}
+
+impl Material {
+    pub(super) fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(Slider::new(&mut self.ka, 0.0..=1.0).text("Ka"));
+        ui.add(Slider::new(&mut self.kd, 0.0..=1.0).text("Kd"));
+        ui.add(Slider::new(&mut self.ks, 0.0..=1.0).text("Ks"));
+        ui.add(Slider::new(&mut self.shininess, 1.0..=200.0).text("Shininess"));
+        ui.checkbox(&mut self.use_vertex_colors, "Use vertex colors");
+        ui.add_enabled_ui(!self.use_vertex_colors, |ui| {
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgb(&mut self.base_color);
+                ui.label("Base color");
+            });
+        });
+    }
+}
@@ -0,0 +1,147 @@
+use glam::Vec3;
+
+use super::{loading::ModelLoadError, ICGJson};
+
+/// Color given to vertices when neither the OBJ nor its material provides one.
+const DEFAULT_COLOR: [f32; 3] = [0.8, 0.8, 0.8];
+
+/// Returns the file name referenced by the first `mtllib` statement, if any.
+/// The name is the rest of the line, so it may contain spaces.
+pub(super) fn find_mtllib(obj: &str) -> Option<&str> {
+    obj.lines().find_map(|line| {
+        let rest = line.trim_start().strip_prefix("mtllib")?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some(rest.trim()).filter(|name| !name.is_empty())
+    })
+}
+
+/// Resolves `name` relative to the directory of `source`.
+pub(super) fn sibling_source(source: &str, name: &str) -> String {
+    match source.rfind('/') {
+        Some(slash) => format!("{}{}", &source[..=slash], name),
+        None => name.to_string(),
+    }
+}
+
+impl ICGJson {
+    /// Converts a Wavefront OBJ (and optionally its MTL) into the flat triangle
+    /// list layout used by the course JSON models.
+    pub(super) fn from_obj(obj: &str, mtl: Option<&str>) -> Result<Self, ModelLoadError> {
+        let (models, materials) = tobj::load_obj_buf(
+            &mut obj.as_bytes(),
+            &tobj::GPU_LOAD_OPTIONS,
+            |_path| match mtl {
+                Some(mtl) => tobj::load_mtl_buf(&mut mtl.as_bytes()),
+                None => Err(tobj::LoadError::OpenFileFailed),
+            },
+        )?;
+        // A missing or broken material file shouldn't stop the mesh from loading.
+        let materials = materials.unwrap_or_default();
+
+        let mut json = ICGJson {
+            vertex_positions: Vec::new(),
+            vertex_normals: Vec::new(),
+            vertex_frontcolors: Vec::new(),
+            vertex_backcolors: Vec::new(),
+            vertex_texture_coords: Vec::new(),
+        };
+        // Texture coordinates are all or nothing, meshes without them get zeros
+        // so the list stays aligned with the positions.
+        let has_texcoords = models.iter().any(|model| !model.mesh.texcoords.is_empty());
+
+        for model in &models {
+            let mesh = &model.mesh;
+            let normals = if mesh.normals.is_empty() {
+                generate_normals(&mesh.positions, &mesh.indices)
+            } else {
+                mesh.normals.clone()
+            };
+            let material_color = mesh
+                .material_id
+                .and_then(|id| materials.get(id))
+                .and_then(|material| material.diffuse)
+                .unwrap_or(DEFAULT_COLOR);
+
+            for &index in &mesh.indices {
+                let i = index as usize;
+                json.vertex_positions
+                    .extend_from_slice(&mesh.positions[3 * i..3 * i + 3]);
+                json.vertex_normals
+                    .extend_from_slice(&normals[3 * i..3 * i + 3]);
+
+                let color = if mesh.vertex_color.is_empty() {
+                    material_color
+                } else {
+                    [
+                        mesh.vertex_color[3 * i],
+                        mesh.vertex_color[3 * i + 1],
+                        mesh.vertex_color[3 * i + 2],
+                    ]
+                };
+                json.vertex_frontcolors.extend_from_slice(&color);
+                json.vertex_backcolors.extend_from_slice(&color);
+
+                if !mesh.texcoords.is_empty() {
+                    json.vertex_texture_coords
+                        .extend_from_slice(&mesh.texcoords[2 * i..2 * i + 2]);
+                } else if has_texcoords {
+                    json.vertex_texture_coords.extend_from_slice(&[0., 0.]);
+                }
+            }
+        }
+
+        Ok(json)
+    }
+}
+
+/// Smooth per-vertex normals, averaged from the (area weighted) normals of
+/// every triangle sharing the vertex.
+pub(super) fn generate_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
+    let vertex = |i: u32| Vec3::from_slice(&positions[3 * i as usize..]);
+
+    let mut normals = vec![Vec3::ZERO; positions.len() / 3];
+    for tri in indices.chunks_exact(3) {
+        let (a, b, c) = (vertex(tri[0]), vertex(tri[1]), vertex(tri[2]));
+        let face = (b - a).cross(c - a);
+        for &i in tri {
+            normals[i as usize] += face;
+        }
+    }
+
+    normals
+        .into_iter()
+        .flat_map(|n| n.normalize_or(Vec3::Z).to_array())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mtllib_name_with_spaces() {
+        let obj = "# cube\nmtllib  my materials.mtl  \nv 0 0 0\n";
+        assert_eq!(find_mtllib(obj), Some("my materials.mtl"));
+    }
+
+    #[test]
+    fn mtllib_needs_a_name() {
+        assert_eq!(find_mtllib("mtllib\nmtllibs other.mtl\n"), None);
+        assert_eq!(find_mtllib("mtllib\tcube.mtl"), Some("cube.mtl"));
+    }
+
+    #[test]
+    fn texcoords_stay_aligned() {
+        let obj = "o a\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n\
+                   o b\nv 0 0 1\nv 1 0 1\nv 0 1 1\nvt 0.5 0.5\nf 4/1 5/1 6/1\n";
+        let json = ICGJson::from_obj(obj, None).unwrap();
+        assert_eq!(json.vertex_positions.len(), 18);
+        assert_eq!(json.vertex_texture_coords.len(), 12);
+        assert_eq!(json.vertex_texture_coords[..6], [0.; 6]);
+
+        let json = ICGJson::from_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n", None).unwrap();
+        assert!(json.vertex_texture_coords.is_empty());
+    }
+}
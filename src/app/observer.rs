@@ -0,0 +1,62 @@
+use egui::{Color32, Stroke};
+use glam::{Mat4, Vec3};
+
+use super::{picking::paint_box_edges, viewport::Pane, DemoApp};
+
+const FRUSTUM_COLOR: Color32 = Color32::from_rgb(240, 240, 120);
+
+/// Corners of the volume `clip_matrix` maps into clip space, bit 2 of the
+/// index picks the far plane.
+fn frustum_corners(clip_matrix: Mat4) -> [Vec3; 8] {
+    let inverse = clip_matrix.inverse();
+    std::array::from_fn(|i| {
+        let sign = |bit: usize| if i & bit != 0 { 1. } else { -1. };
+        inverse.project_point3(Vec3::new(sign(1), sign(2), sign(4)))
+    })
+}
+
+impl DemoApp {
+    /// World to clip space of the main camera, the same matrix
+    /// [`super::GLStuff::paint`] draws it with.
+    pub(super) fn main_clip_matrix(&self) -> Option<Mat4> {
+        let scene_data = self.get_scene_data(&Pane {
+            main_camera: true,
+            ..Default::default()
+        });
+        let stuff = self.gl_stuff.lock();
+        Some(
+            stuff
+                .as_ref()?
+                .projection_matrix(&scene_data, self.main_aspect)
+                * scene_data.view_matrix(),
+        )
+    }
+
+    /// Draws the main camera's frustum as a wireframe over the pane `pane`
+    /// if it is an observer.
+    pub(super) fn paint_observed_frustum(&self, ui: &egui::Ui, pane: usize, rect: egui::Rect) {
+        let observer = &self.panes[pane];
+        if !observer.observer || observer.main_camera {
+            return;
+        }
+        let (Some(clip_matrix), Some(view)) = (self.main_clip_matrix(), self.pane_view(pane, rect))
+        else {
+            return;
+        };
+        let corners = frustum_corners(clip_matrix);
+        let painter = ui.painter().with_clip_rect(rect);
+        paint_box_edges(&painter, &view, corners, Stroke::new(1.5, FRUSTUM_COLOR));
+        // The near plane, where the picture is.
+        let near: Option<Vec<_>> = [0, 1, 3, 2]
+            .into_iter()
+            .map(|i| view.to_screen(corners[i]))
+            .collect();
+        if let Some(near) = near {
+            painter.add(egui::Shape::convex_polygon(
+                near,
+                FRUSTUM_COLOR.gamma_multiply(0.2),
+                Stroke::NONE,
+            ));
+        }
+    }
+}
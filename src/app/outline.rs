@@ -0,0 +1,113 @@
+use eframe::{egui_glow::ShaderVersion, glow};
+use glam::{Mat4, Vec4};
+use glow::HasContext as _;
+
+use super::{clipping, GLStuff, ICGLoaded};
+
+/// Outline thickness in pixels.
+const OUTLINE_WIDTH: f32 = 3.;
+const OUTLINE_COLOR: [f32; 3] = [1., 0.6, 0.1];
+
+/// Draws a highlight around the selected objects by rendering them a bit
+/// inflated in a flat color.
+pub(super) struct Outline {
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+}
+
+#[allow(unsafe_code)] // we need unsafe code to use glow
+impl Outline {
+    pub(super) fn new(shader_version: ShaderVersion, gl: &glow::Context) -> Self {
+        unsafe {
+            let program = GLStuff::create_program(
+                shader_version,
+                include_str!("../outline_vertex.glsl"),
+                &format!(
+                    "{}\n{}",
+                    clipping::shader_source(),
+                    include_str!("../outline_fragment.glsl")
+                ),
+                gl,
+            );
+
+            let vertex_array = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vertex_array));
+            for name in ["aVertexPosition", "aVertexNormal"] {
+                gl.enable_vertex_attrib_array(gl.get_attrib_location(program, name).unwrap());
+            }
+            gl.bind_vertex_array(None);
+
+            Self {
+                program,
+                vertex_array,
+            }
+        }
+    }
+
+    pub(super) fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.vertex_array);
+        }
+    }
+
+    /// Draws the outline of `objects` (model matrix, mesh and clipping
+    /// planes) without writing
+    /// depth, so drawing them afterwards covers everything but the rim.
+    /// Leaves its own program in use.
+    pub(super) fn render(
+        &self,
+        gl: &glow::Context,
+        objects: &[(Mat4, &ICGLoaded, &[Vec4])],
+        perspective_mat: Mat4,
+        width: i32,
+        height: i32,
+    ) {
+        if objects.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.uniform_matrix_4_f32_slice(
+                gl.get_uniform_location(self.program, "uPMatrix").as_ref(),
+                false,
+                &perspective_mat.to_cols_array(),
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "uViewport").as_ref(),
+                width as f32,
+                height as f32,
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "uWidth").as_ref(),
+                OUTLINE_WIDTH,
+            );
+            gl.uniform_3_f32_slice(
+                gl.get_uniform_location(self.program, "uColor").as_ref(),
+                &OUTLINE_COLOR,
+            );
+            let mv_mat_loc = gl.get_uniform_location(self.program, "uMVMatrix");
+            let vertex_position_loc = gl
+                .get_attrib_location(self.program, "aVertexPosition")
+                .unwrap();
+            let vertex_normal_loc = gl
+                .get_attrib_location(self.program, "aVertexNormal")
+                .unwrap();
+
+            gl.depth_mask(false);
+            gl.bind_vertex_array(Some(self.vertex_array));
+            for (mv_mat, model, clipping_planes) in objects {
+                clipping::set_uniforms(gl, self.program, clipping_planes);
+                gl.uniform_matrix_4_f32_slice(mv_mat_loc.as_ref(), false, &mv_mat.to_cols_array());
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(model.pos_buffer));
+                gl.vertex_attrib_pointer_f32(vertex_position_loc, 3, glow::FLOAT, false, 0, 0);
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(model.norm_buffer));
+                gl.vertex_attrib_pointer_f32(vertex_normal_loc, 3, glow::FLOAT, false, 0, 0);
+                gl.draw_arrays(glow::TRIANGLES, 0, model.item_count);
+            }
+            gl.bind_vertex_array(None);
+            gl.depth_mask(true);
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use egui::{Pos2, Rect, Stroke};
+use glam::{vec2, Mat4, Vec2, Vec3, Vec4};
+
+use super::{
+    camera::{self, Projection, ProjectionKind},
+    clipping, DemoApp, GLStuff, SceneData,
+};
+
+/// A ray `origin + t * dir`, `t` going from 0 on the near plane to 1 on the
+/// far plane for rays made by [`Ray::unproject`].
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Ray {
+    pub(super) origin: Vec3,
+    pub(super) dir: Vec3,
+}
+
+impl Ray {
+    /// The ray through `ndc` (normalized device coordinates) of a view
+    /// projection matrix.
+    pub(super) fn unproject(view_proj: Mat4, ndc: Vec2) -> Self {
+        let inverse = view_proj.inverse();
+        let near = inverse.project_point3(ndc.extend(-1.));
+        let far = inverse.project_point3(ndc.extend(1.));
+        Self {
+            origin: near,
+            dir: far - near,
+        }
+    }
+
+    pub(super) fn at(&self, t: f32) -> Vec3 {
+        self.origin + t * self.dir
+    }
+
+    /// The same ray in another space, `t` stays the same for affine `mat`.
+    pub(super) fn transformed(&self, mat: Mat4) -> Self {
+        Self {
+            origin: mat.transform_point3(self.origin),
+            dir: mat.transform_vector3(self.dir),
+        }
+    }
+
+    /// Whether the `0..=1` part of the ray goes through an axis aligned box.
+    fn hits_box(&self, [min, max]: [Vec3; 2]) -> bool {
+        let inverse = self.dir.recip();
+        let (t0, t1) = ((min - self.origin) * inverse, (max - self.origin) * inverse);
+        t0.min(t1).max_element().max(0.) <= t0.max(t1).min_element().min(1.)
+    }
+
+    /// Möller–Trumbore, both sides of the triangle count.
+    fn triangle(&self, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+        let (ab, ac) = (b - a, c - a);
+        let p = self.dir.cross(ac);
+        let det = ab.dot(p);
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) / det;
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(ab);
+        let v = self.dir.dot(q) / det;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+        Some(ac.dot(q) / det).filter(|t| (0. ..=1.).contains(t))
+    }
+}
+
+/// How a pane maps the world onto the screen.
+pub(super) struct PaneView {
+    pub(super) rect: Rect,
+    pub(super) view_proj: Mat4,
+    pub(super) eye: Vec3,
+    pub(super) forward: Vec3,
+    projection: Projection,
+    fovy: f32,
+}
+
+impl PaneView {
+    pub(super) fn new(scene_data: &SceneData, stuff: &GLStuff, rect: Rect) -> Self {
+        Self {
+            rect,
+            view_proj: stuff.projection_matrix(scene_data, rect.aspect_ratio())
+                * scene_data.view_matrix(),
+            eye: scene_data.camera_pos,
+            forward: camera::forward(scene_data.camera_phi, scene_data.camera_up),
+            projection: scene_data.projection,
+            fovy: scene_data.fovy,
+        }
+    }
+
+    /// The ray under a point of the pane.
+    pub(super) fn ray(&self, pos: Pos2) -> Ray {
+        let uv = (pos - self.rect.min) / self.rect.size();
+        Ray::unproject(self.view_proj, vec2(uv.x * 2. - 1., 1. - uv.y * 2.))
+    }
+
+    /// Where `point` ends up in the pane, if it is in front of the camera.
+    pub(super) fn to_screen(&self, point: Vec3) -> Option<Pos2> {
+        let clip = self.view_proj * point.extend(1.);
+        if clip.w <= f32::EPSILON {
+            return None;
+        }
+        Some(self.clip_to_screen(clip))
+    }
+
+    /// The part of the segment from `from` to `to` in front of the near
+    /// plane, in the pane. The segment is cut in clip space, before the
+    /// divide by w flips whatever is behind the camera.
+    pub(super) fn segment_to_screen(&self, from: Vec3, to: Vec3) -> Option<[Pos2; 2]> {
+        let (mut a, mut b) = (
+            self.view_proj * from.extend(1.),
+            self.view_proj * to.extend(1.),
+        );
+        // Signed distances to the near plane, z = -w.
+        let (da, db) = (a.z + a.w, b.z + b.w);
+        if da < 0. && db < 0. {
+            return None;
+        }
+        if da < 0. {
+            a = a.lerp(b, da / (da - db));
+        } else if db < 0. {
+            b = b.lerp(a, db / (db - da));
+        }
+        if a.w <= f32::EPSILON || b.w <= f32::EPSILON {
+            return None;
+        }
+        Some([self.clip_to_screen(a), self.clip_to_screen(b)])
+    }
+
+    fn clip_to_screen(&self, clip: Vec4) -> Pos2 {
+        let ndc = clip.truncate() / clip.w;
+        self.rect.min + self.rect.size() * egui::vec2(ndc.x + 1., 1. - ndc.y) / 2.
+    }
+
+    /// The direction from `point` toward the viewer.
+    pub(super) fn toward_eye(&self, point: Vec3) -> Vec3 {
+        match self.projection.kind {
+            ProjectionKind::Perspective => (self.eye - point).normalize_or_zero(),
+            ProjectionKind::Orthographic => -self.forward,
+        }
+    }
+
+    /// World units a pixel covers at `point`.
+    pub(super) fn units_per_pixel(&self, point: Vec3) -> f32 {
+        let distance = (point - self.eye).dot(self.forward).max(f32::EPSILON);
+        self.projection
+            .units_per_pixel(self.fovy, distance, self.rect.height())
+    }
+}
+
+/// Draws the twelve edges of a box given by its `corners`, where corners one
+/// bit of the index apart share an edge.
+pub(super) fn paint_box_edges(
+    painter: &egui::Painter,
+    view: &PaneView,
+    corners: [Vec3; 8],
+    stroke: Stroke,
+) {
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                if let Some(segment) = view.segment_to_screen(corners[i], corners[i | bit]) {
+                    painter.line_segment(segment, stroke);
+                }
+            }
+        }
+    }
+}
+
+impl GLStuff {
+    /// The closest object `ray` (in world space) hits and where along it,
+    /// skipping what the clipping planes cut away.
+    pub(super) fn pick(&self, scene_data: &SceneData, ray: Ray) -> Option<(usize, f32)> {
+        scene_data
+            .objs
+            .iter()
+            .enumerate()
+            .filter_map(|(id, obj)| {
+                let model = self.model(obj.model_id);
+                let local = ray.transformed(obj.mv_mat.inverse());
+                if !local.origin.is_finite() || !local.dir.is_finite() {
+                    return None;
+                }
+                if !local.hits_box(model.bounds) {
+                    return None;
+                }
+                model
+                    .positions
+                    .chunks_exact(3)
+                    .filter_map(|triangle| local.triangle([triangle[0], triangle[1], triangle[2]]))
+                    .filter(|&t| clipping::keeps(&obj.clipping, ray.at(t)))
+                    .min_by(f32::total_cmp)
+                    .map(|t| (id, t))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+impl DemoApp {
+    /// The object under `pos` in the pane `pane` drawn at `rect`.
+    pub(super) fn pick(&self, pane: usize, rect: Rect, pos: Pos2) -> Option<usize> {
+        let scene_data = self.get_scene_data(&self.panes[pane]);
+        let stuff = self.gl_stuff.lock();
+        let stuff = stuff.as_ref()?;
+        let ray = PaneView::new(&scene_data, stuff, rect).ray(pos);
+        stuff.pick(&scene_data, ray).map(|(id, _)| id)
+    }
+
+    /// How the pane `pane` drawn at `rect` sees the world.
+    pub(super) fn pane_view(&self, pane: usize, rect: Rect) -> Option<PaneView> {
+        let scene_data = self.get_scene_data(&self.panes[pane]);
+        let stuff = self.gl_stuff.lock();
+        Some(PaneView::new(&scene_data, stuff.as_ref()?, rect))
+    }
+
+    /// Selects only `id`, or with `extend` adds it to the selection (removing
+    /// it if it already was). Clicking nothing without `extend` clears it.
+    pub(super) fn select(&mut self, id: Option<usize>, extend: bool) {
+        match (id, extend) {
+            (Some(id), true) => {
+                if self.selection.remove(&id) {
+                    if self.selected_object == Some(id) {
+                        self.selected_object = self.selection.last().copied();
+                    }
+                } else {
+                    self.selection.insert(id);
+                    self.selected_object = Some(id);
+                }
+            }
+            (None, true) => {}
+            (id, false) => {
+                self.selection = id.into_iter().collect();
+                self.selected_object = id;
+            }
+        }
+    }
+
+    /// Drops removed objects from the selection and makes sure it contains
+    /// the selected object.
+    pub(super) fn fix_selection(&mut self) {
+        let count = self.objects.len();
+        self.selected_object = self.selected_object.filter(|&id| id < count);
+        self.selection.retain(|&id| id < count);
+        self.selection.extend(self.selected_object);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    /// A unit triangle in the z = 0 plane, facing +z.
+    const TRIANGLE: [Vec3; 3] = [Vec3::ZERO, Vec3::X, Vec3::Y];
+
+    /// Going down -z from z = 1 to z = -1.
+    fn down_at(x: f32, y: f32) -> Ray {
+        Ray {
+            origin: vec3(x, y, 1.),
+            dir: vec3(0., 0., -2.),
+        }
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let t = down_at(0.25, 0.25).triangle(TRIANGLE).unwrap();
+        assert!((t - 0.5).abs() < 1e-6);
+        assert!(down_at(0.25, 0.25)
+            .at(t)
+            .abs_diff_eq(vec3(0.25, 0.25, 0.), 1e-6));
+    }
+
+    #[test]
+    fn triangle_miss() {
+        assert_eq!(down_at(0.75, 0.75).triangle(TRIANGLE), None);
+        assert_eq!(down_at(-0.1, 0.5).triangle(TRIANGLE), None);
+        // Parallel to the triangle.
+        let parallel = Ray {
+            origin: vec3(-1., 0.25, 0.),
+            dir: Vec3::X,
+        };
+        assert_eq!(parallel.triangle(TRIANGLE), None);
+    }
+
+    #[test]
+    fn triangle_back_face_counts() {
+        let up = Ray {
+            origin: vec3(0.25, 0.25, -1.),
+            dir: vec3(0., 0., 2.),
+        };
+        let t = up.triangle(TRIANGLE).unwrap();
+        assert!((t - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn triangle_outside_the_segment() {
+        // Stops before reaching the triangle.
+        let short = Ray {
+            origin: vec3(0.25, 0.25, 1.),
+            dir: vec3(0., 0., -0.5),
+        };
+        assert_eq!(short.triangle(TRIANGLE), None);
+        // The triangle is behind the origin.
+        let away = Ray {
+            origin: vec3(0.25, 0.25, 1.),
+            dir: vec3(0., 0., 2.),
+        };
+        assert_eq!(away.triangle(TRIANGLE), None);
+    }
+
+    #[test]
+    fn box_hits_and_misses() {
+        let unit = [Vec3::splat(-1.), Vec3::ONE];
+        assert!(down_at(0., 0.).hits_box([Vec3::splat(-0.5), Vec3::splat(0.5)]));
+        assert!(!down_at(2., 0.).hits_box(unit));
+        // Starting inside counts.
+        let inside = Ray {
+            origin: Vec3::ZERO,
+            dir: Vec3::X,
+        };
+        assert!(inside.hits_box(unit));
+        // The box is past the end of the segment, or behind the origin.
+        let short = Ray {
+            origin: vec3(0., 0., 5.),
+            dir: vec3(0., 0., -2.),
+        };
+        assert!(!short.hits_box(unit));
+        let away = Ray {
+            origin: vec3(0., 0., 5.),
+            dir: vec3(0., 0., 2.),
+        };
+        assert!(!away.hits_box(unit));
+        // Axis aligned rays have infinite inverse components.
+        let along_edge = Ray {
+            origin: vec3(1., 1., 5.),
+            dir: vec3(0., 0., -10.),
+        };
+        assert!(along_edge.hits_box(unit));
+    }
+
+    #[test]
+    fn unproject_spans_near_to_far() {
+        let projection = Mat4::perspective_rh_gl(1., 1., 0.1, 100.);
+        let view = Mat4::look_at_rh(vec3(0., 0., 10.), Vec3::ZERO, Vec3::Y);
+        let ray = Ray::unproject(projection * view, Vec2::ZERO);
+        assert!(ray.origin.abs_diff_eq(vec3(0., 0., 9.9), 1e-3));
+        assert!(ray.at(1.).abs_diff_eq(vec3(0., 0., -90.), 1e-2));
+        // Off center rays go through the matching side of the view.
+        let right = Ray::unproject(projection * view, vec2(1., 0.));
+        assert!(right.dir.x > 0. && right.dir.y.abs() < 1e-4);
+    }
+
+    #[test]
+    fn transformed_keeps_t() {
+        let ray = down_at(0.25, 0.25);
+        let mat = Mat4::from_scale_rotation_translation(
+            Vec3::splat(3.),
+            glam::Quat::from_rotation_y(0.7),
+            vec3(1., 2., 3.),
+        );
+        let moved = ray.transformed(mat);
+        assert!(moved
+            .at(0.3)
+            .abs_diff_eq(mat.transform_point3(ray.at(0.3)), 1e-5));
+    }
+
+    #[test]
+    fn segments_are_cut_at_the_near_plane() {
+        // Looking down -z from the origin, with the near plane at z = -1.
+        let view = PaneView {
+            rect: Rect::from_min_size(Pos2::ZERO, egui::vec2(100., 100.)),
+            view_proj: Mat4::perspective_rh_gl(90f32.to_radians(), 1., 1., 10.),
+            eye: Vec3::ZERO,
+            forward: Vec3::NEG_Z,
+            projection: Projection::default(),
+            fovy: 90.,
+        };
+        let center = Pos2::new(50., 50.);
+
+        let [from, to] = view
+            .segment_to_screen(vec3(0., 0., -5.), vec3(0., 0., 5.))
+            .unwrap();
+        assert!(from.distance(center) < 1e-3 && to.distance(center) < 1e-3);
+
+        // Crossing the near plane halfway, at x = 1, the right edge.
+        let [from, to] = view
+            .segment_to_screen(vec3(0., 0., -2.), vec3(2., 0., 0.))
+            .unwrap();
+        assert!(from.distance(center) < 1e-3);
+        assert!(to.distance(Pos2::new(100., 50.)) < 1e-3);
+        assert!(view.to_screen(vec3(2., 0., 0.)).is_none());
+
+        assert!(view
+            .segment_to_screen(vec3(0., 0., 1.), vec3(1., 1., -0.5))
+            .is_none());
+    }
+}
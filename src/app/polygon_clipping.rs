@@ -0,0 +1,456 @@
+use eframe::glow;
+use glam::{Mat4, Vec3, Vec4};
+use glow::HasContext as _;
+
+use super::{clipping::ClippingMode, GLStuff, ICGJson, ICGLoaded, RenderedObject, SceneData};
+
+const EDGE_COLOR: [f32; 3] = [1., 0.9, 0.2];
+
+/// A corner of a polygon with everything that gets interpolated along a cut.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) struct Vertex {
+    pub(super) position: Vec3,
+    pub(super) normal: Vec3,
+    pub(super) color: Vec3,
+    /// Bit `i` is set when the vertex lies on plane `i`, because clipping by
+    /// that plane made it.
+    on_planes: u32,
+}
+
+impl Vertex {
+    pub(super) fn new(position: Vec3, normal: Vec3, color: Vec3) -> Self {
+        Self {
+            position,
+            normal,
+            color,
+            on_planes: 0,
+        }
+    }
+
+    /// Where the edge to `other` crosses the plane, given the signed
+    /// distances of both ends to it.
+    fn intersect(self, other: Self, distance: f32, other_distance: f32) -> Self {
+        let t = distance / (distance - other_distance);
+        Self {
+            position: self.position.lerp(other.position, t),
+            normal: self.normal.lerp(other.normal, t).normalize_or_zero(),
+            color: self.color.lerp(other.color, t),
+            on_planes: self.on_planes & other.on_planes,
+        }
+    }
+}
+
+/// Positive on the side of `plane` that is kept.
+fn signed_distance(plane: Vec4, point: Vec3) -> f32 {
+    plane.truncate().dot(point) + plane.w
+}
+
+/// One step of Sutherland–Hodgman: the part of the convex `polygon` on the
+/// kept side of `plane`, which is plane number `id` of the ones clipped by.
+pub(super) fn clip_polygon(polygon: &[Vertex], plane: Vec4, id: usize) -> Vec<Vertex> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    let Some(&last) = polygon.last() else {
+        return clipped;
+    };
+    let (mut previous, mut previous_distance) = (last, signed_distance(plane, last.position));
+    for &vertex in polygon {
+        let distance = signed_distance(plane, vertex.position);
+        // An edge going from one side to the other is cut where it
+        // crosses, ends right on the plane need no new vertex.
+        if distance * previous_distance < 0. {
+            let mut cut = previous.intersect(vertex, previous_distance, distance);
+            cut.on_planes |= 1 << id;
+            clipped.push(cut);
+        }
+        if distance >= 0. {
+            let mut vertex = vertex;
+            if distance == 0. {
+                vertex.on_planes |= 1 << id;
+            }
+            clipped.push(vertex);
+        }
+        (previous, previous_distance) = (vertex, distance);
+    }
+    clipped
+}
+
+/// Splits a convex polygon into a fan of triangles around its first corner.
+pub(super) fn triangulate(polygon: &[Vertex]) -> impl Iterator<Item = [Vertex; 3]> + '_ {
+    polygon
+        .windows(2)
+        .skip(1)
+        .map(|pair| [polygon[0], pair[0], pair[1]])
+}
+
+/// What is left of a mesh after clipping.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(super) struct ClippedMesh {
+    pub(super) triangles: Vec<[Vertex; 3]>,
+    /// Segments where a plane cut through a triangle.
+    pub(super) edges: Vec<[Vec3; 2]>,
+}
+
+/// Clips every triangle by every plane, keeping what is on the positive side
+/// of all of them.
+pub(super) fn clip_mesh(
+    triangles: impl IntoIterator<Item = [Vertex; 3]>,
+    planes: &[Vec4],
+) -> ClippedMesh {
+    let mut mesh = ClippedMesh::default();
+    for triangle in triangles {
+        let polygon = planes
+            .iter()
+            .enumerate()
+            .fold(triangle.to_vec(), |polygon, (id, &plane)| {
+                clip_polygon(&polygon, plane, id)
+            });
+        if polygon.len() < 3 {
+            continue;
+        }
+        // Both ends on the same plane means the edge runs along the cut.
+        for (i, &vertex) in polygon.iter().enumerate() {
+            let next = polygon[(i + 1) % polygon.len()];
+            if vertex.on_planes & next.on_planes != 0 {
+                mesh.edges.push([vertex.position, next.position]);
+            }
+        }
+        mesh.triangles.extend(triangulate(&polygon));
+    }
+    mesh
+}
+
+/// Vertex arrays for [`ICGJson::load_model`], three vertices per triangle.
+fn to_json(vertices: impl Iterator<Item = Vertex>) -> ICGJson {
+    let mut json = ICGJson {
+        vertex_positions: Vec::new(),
+        vertex_normals: Vec::new(),
+        vertex_frontcolors: Vec::new(),
+        vertex_backcolors: Vec::new(),
+        vertex_texture_coords: Vec::new(),
+    };
+    for vertex in vertices {
+        json.vertex_positions.extend(vertex.position.to_array());
+        json.vertex_normals.extend(vertex.normal.to_array());
+        json.vertex_frontcolors.extend(vertex.color.to_array());
+    }
+    json
+}
+
+/// An object's mesh clipped on the CPU, with what it was clipped from so it
+/// is only redone when that changes.
+pub(super) struct ClippedModel {
+    source: glow::Buffer,
+    /// In model space.
+    planes: Vec<Vec4>,
+    mesh: ICGLoaded,
+    /// Drawn as lines, two vertices per edge.
+    edges: ICGLoaded,
+}
+
+impl ClippedModel {
+    fn new(
+        gl: &glow::Context,
+        vao: glow::VertexArray,
+        model: &ICGLoaded,
+        planes: Vec<Vec4>,
+    ) -> Self {
+        let clipped = clip_mesh(model.triangles(), &planes);
+        let mesh = to_json(clipped.triangles.into_iter().flatten()).load_model(vao, gl);
+        let edges = to_json(
+            clipped
+                .edges
+                .into_iter()
+                .flatten()
+                .map(|position| Vertex::new(position, Vec3::ZERO, Vec3::ONE)),
+        )
+        .load_model(vao, gl);
+        Self {
+            source: model.pos_buffer,
+            planes,
+            mesh,
+            edges,
+        }
+    }
+
+    pub(super) fn destroy(&self, gl: &glow::Context) {
+        self.mesh.destroy(gl);
+        self.edges.destroy(gl);
+    }
+}
+
+impl ICGLoaded {
+    /// The mesh as triangles with everything clipping interpolates.
+    fn triangles(&self) -> impl Iterator<Item = [Vertex; 3]> + '_ {
+        let vertex = |i: usize| {
+            Vertex::new(
+                self.positions[i],
+                self.normals.get(i).copied().unwrap_or(Vec3::Z),
+                self.colors.get(i).copied().unwrap_or(Vec3::ONE),
+            )
+        };
+        (0..self.positions.len() / 3)
+            .map(move |i| [vertex(3 * i), vertex(3 * i + 1), vertex(3 * i + 2)])
+    }
+}
+
+impl GLStuff {
+    /// Clips the objects of `scene_data` on the CPU, only redoing the ones
+    /// whose mesh or planes changed, and frees the rest.
+    pub(super) fn update_clipped(&mut self, gl: &glow::Context, scene_data: &SceneData) {
+        let mut old_clipped = std::mem::take(&mut self.clipped);
+        if scene_data.clipping_mode == ClippingMode::Cpu {
+            for (id, obj) in scene_data.objs.iter().enumerate() {
+                if obj.clipping.is_empty() {
+                    continue;
+                }
+                let model = self.model(obj.model_id);
+                // Moving the planes instead of the mesh keeps the result
+                // valid while the object and the planes move together.
+                let planes: Vec<_> = obj
+                    .clipping
+                    .iter()
+                    .map(|&plane| obj.mv_mat.transpose() * plane)
+                    .collect();
+                let entry = match old_clipped.remove(&id) {
+                    Some(old) if old.source == model.pos_buffer && old.planes == planes => old,
+                    old => {
+                        if let Some(old) = old {
+                            old.destroy(gl);
+                        }
+                        ClippedModel::new(gl, self.vertex_array, model, planes)
+                    }
+                };
+                self.clipped.insert(id, entry);
+            }
+        }
+        for old in old_clipped.values() {
+            old.destroy(gl);
+        }
+    }
+
+    /// The mesh to draw for object `id` and the planes the GPU still has to
+    /// clip it by.
+    pub(super) fn clipped_mesh<'a>(
+        &'a self,
+        id: usize,
+        obj: &'a RenderedObject,
+    ) -> (&'a ICGLoaded, &'a [Vec4]) {
+        match self.clipped.get(&id) {
+            Some(clipped) => (&clipped.mesh, &[]),
+            None => (self.model(obj.model_id), &obj.clipping),
+        }
+    }
+
+    /// Draws the edges clipping on the CPU made as lines, with the light
+    /// program. Leaves that program in use.
+    #[allow(unsafe_code)] // we need unsafe code to use glow
+    pub(super) fn paint_clipped_edges(
+        &self,
+        gl: &glow::Context,
+        scene_data: &SceneData,
+        perspective_mat: Mat4,
+    ) {
+        if self.clipped.is_empty() {
+            return;
+        }
+        unsafe {
+            gl.use_program(Some(self.light_program));
+            gl.uniform_matrix_4_f32_slice(
+                gl.get_uniform_location(self.light_program, "uPMatrix")
+                    .as_ref(),
+                false,
+                &perspective_mat.to_cols_array(),
+            );
+            gl.uniform_3_f32_slice(
+                gl.get_uniform_location(self.light_program, "ambient_color")
+                    .as_ref(),
+                &EDGE_COLOR,
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.light_program, "Ka").as_ref(),
+                1.0,
+            );
+            let mv_mat_loc = gl.get_uniform_location(self.light_program, "uMVMatrix");
+            let vertex_position_loc = gl
+                .get_attrib_location(self.light_program, "aVertexPosition")
+                .unwrap();
+            let front_color_loc = gl
+                .get_attrib_location(self.light_program, "aFrontColor")
+                .unwrap();
+
+            // The edges lie right on the surfaces they border.
+            gl.depth_func(glow::LEQUAL);
+            gl.bind_vertex_array(Some(self.light_vao));
+            for (id, clipped) in &self.clipped {
+                let Some(obj) = scene_data.objs.get(*id) else {
+                    continue;
+                };
+                let edges = &clipped.edges;
+                gl.uniform_matrix_4_f32_slice(
+                    mv_mat_loc.as_ref(),
+                    false,
+                    &obj.mv_mat.to_cols_array(),
+                );
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(edges.pos_buffer));
+                gl.vertex_attrib_pointer_f32(vertex_position_loc, 3, glow::FLOAT, false, 0, 0);
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(edges.color_buffer));
+                gl.vertex_attrib_pointer_f32(front_color_loc, 3, glow::FLOAT, false, 0, 0);
+                gl.draw_arrays(glow::LINES, 0, edges.item_count);
+            }
+            gl.bind_vertex_array(None);
+            gl.depth_func(glow::LESS);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    fn vertex(x: f32, y: f32, z: f32) -> Vertex {
+        Vertex::new(vec3(x, y, z), Vec3::Z, Vec3::ONE)
+    }
+
+    fn triangle() -> [Vertex; 3] {
+        [vertex(0., 0., 0.), vertex(2., 0., 0.), vertex(0., 2., 0.)]
+    }
+
+    fn area(triangles: &[[Vertex; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|[a, b, c]| {
+                (b.position - a.position)
+                    .cross(c.position - a.position)
+                    .length()
+                    / 2.
+            })
+            .sum()
+    }
+
+    /// Keeps `x <= at`.
+    fn below_x(at: f32) -> Vec4 {
+        Vec4::new(-1., 0., 0., at)
+    }
+
+    #[test]
+    fn polygon_fully_kept() {
+        let clipped = clip_polygon(&triangle(), below_x(5.), 0);
+        assert_eq!(clipped, triangle().to_vec());
+    }
+
+    #[test]
+    fn polygon_fully_removed() {
+        assert!(clip_polygon(&triangle(), below_x(-1.), 0).is_empty());
+        assert!(clip_polygon(&[], below_x(1.), 0).is_empty());
+    }
+
+    #[test]
+    fn cutting_one_corner_gives_a_quad() {
+        let clipped = clip_polygon(&triangle(), below_x(1.), 0);
+        assert_eq!(clipped.len(), 4);
+        for vertex in &clipped {
+            assert!(vertex.position.x <= 1. + 1e-6);
+        }
+        let cut: Vec<_> = clipped.iter().filter(|v| v.on_planes == 1).collect();
+        assert_eq!(cut.len(), 2);
+        for vertex in cut {
+            assert!((vertex.position.x - 1.).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn keeping_one_corner_gives_a_triangle() {
+        let clipped = clip_polygon(&triangle(), Vec4::new(1., 0., 0., -1.), 0);
+        assert_eq!(clipped.len(), 3);
+        assert_eq!(clipped.iter().filter(|v| v.on_planes == 1).count(), 2);
+        assert!(clipped.contains(&vertex(2., 0., 0.)));
+    }
+
+    #[test]
+    fn vertex_on_the_plane_is_kept_once() {
+        let clipped = clip_polygon(&triangle(), below_x(0.), 0);
+        assert_eq!(clipped.len(), 2);
+        assert!(clipped.iter().all(|v| v.position.x == 0.));
+        let mesh = clip_mesh([triangle()], &[below_x(0.)]);
+        assert!(mesh.triangles.is_empty());
+    }
+
+    #[test]
+    fn cut_through_a_corner_is_highlighted() {
+        // Keeps y >= x, going right through the corner at the origin.
+        let mesh = clip_mesh([triangle()], &[Vec4::new(-1., 1., 0., 0.)]);
+        assert_eq!(mesh.triangles.len(), 1);
+        assert!((area(&mesh.triangles) - 1.).abs() < 1e-5);
+        assert_eq!(mesh.edges, vec![[Vec3::ZERO, vec3(1., 1., 0.)]]);
+    }
+
+    #[test]
+    fn attributes_are_interpolated() {
+        let mut triangle = triangle();
+        triangle[1].color = Vec3::ZERO;
+        triangle[1].normal = Vec3::X;
+        let clipped = clip_polygon(&triangle, below_x(1.), 0);
+        let midpoint = clipped
+            .iter()
+            .find(|v| v.position == vec3(1., 0., 0.))
+            .unwrap();
+        assert!((midpoint.color - Vec3::splat(0.5)).length() < 1e-6);
+        assert!((midpoint.normal - vec3(1., 0., 1.).normalize()).length() < 1e-6);
+    }
+
+    #[test]
+    fn fan_covers_the_polygon() {
+        let square = [
+            vertex(0., 0., 0.),
+            vertex(1., 0., 0.),
+            vertex(1., 1., 0.),
+            vertex(0., 1., 0.),
+        ];
+        let triangles: Vec<_> = triangulate(&square).collect();
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&triangles) - 1.).abs() < 1e-6);
+        assert_eq!(triangulate(&square[..2]).count(), 0);
+    }
+
+    #[test]
+    fn mesh_area_after_a_cut() {
+        // The triangle has area 2, cutting at x = 1 removes a corner of 0.5.
+        let mesh = clip_mesh([triangle()], &[below_x(1.)]);
+        assert_eq!(mesh.triangles.len(), 2);
+        assert!((area(&mesh.triangles) - 1.5).abs() < 1e-5);
+        assert_eq!(mesh.edges.len(), 1);
+        let [a, b] = mesh.edges[0];
+        assert!((a.x - 1.).abs() < 1e-6 && (b.x - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn two_planes_make_two_cut_edges() {
+        // Keeps x <= 1 and y <= 1 of the triangle.
+        let planes = [below_x(1.), Vec4::new(0., -1., 0., 1.)];
+        let mesh = clip_mesh([triangle()], &planes);
+        assert!((area(&mesh.triangles) - 1.).abs() < 1e-5);
+        assert_eq!(mesh.edges.len(), 2);
+        for triangle in &mesh.triangles {
+            for vertex in triangle {
+                assert!(vertex.position.x <= 1. + 1e-6 && vertex.position.y <= 1. + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn no_planes_keeps_everything() {
+        let mesh = clip_mesh([triangle(), triangle()], &[]);
+        assert_eq!(mesh.triangles, vec![triangle(), triangle()]);
+        assert!(mesh.edges.is_empty());
+    }
+
+    #[test]
+    fn plane_scale_does_not_matter() {
+        let a = clip_mesh([triangle()], &[below_x(1.)]);
+        let b = clip_mesh([triangle()], &[below_x(1.) * 3.]);
+        assert_eq!(a.triangles.len(), b.triangles.len());
+        assert!((area(&a.triangles) - area(&b.triangles)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,325 @@
+use egui::{DragValue, Slider};
+use glam::{EulerRot, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
+
+use super::CGObject;
+
+/// How an object's rotation is stored and edited.
+#[derive(
+    Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize, Default, IntoStaticStr, EnumIter,
+)]
+pub(super) enum RotationMode {
+    /// Euler angles applied as X * Y * Z, what every object used to have.
+    #[default]
+    #[strum(serialize = "Euler XYZ")]
+    EulerXyz,
+    #[strum(serialize = "Euler XZY")]
+    EulerXzy,
+    #[strum(serialize = "Euler YXZ")]
+    EulerYxz,
+    #[strum(serialize = "Euler YZX")]
+    EulerYzx,
+    #[strum(serialize = "Euler ZXY")]
+    EulerZxy,
+    #[strum(serialize = "Euler ZYX")]
+    EulerZyx,
+    #[strum(serialize = "Axis-angle")]
+    AxisAngle,
+    Quaternion,
+}
+
+impl RotationMode {
+    /// The glam rotation sequence and the axes it takes its angles from, for
+    /// the Euler modes.
+    fn euler(self) -> Option<(EulerRot, [usize; 3])> {
+        Some(match self {
+            Self::EulerXyz => (EulerRot::XYZ, [0, 1, 2]),
+            Self::EulerXzy => (EulerRot::XZY, [0, 2, 1]),
+            Self::EulerYxz => (EulerRot::YXZ, [1, 0, 2]),
+            Self::EulerYzx => (EulerRot::YZX, [1, 2, 0]),
+            Self::EulerZxy => (EulerRot::ZXY, [2, 0, 1]),
+            Self::EulerZyx => (EulerRot::ZYX, [2, 1, 0]),
+            Self::AxisAngle | Self::Quaternion => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub(super) struct AxisAngle {
+    pub(super) axis: Vec3,
+    /// In degrees.
+    pub(super) angle: f32,
+}
+
+impl Default for AxisAngle {
+    fn default() -> Self {
+        Self {
+            axis: Vec3::Y,
+            angle: 0.,
+        }
+    }
+}
+
+/// Wraps an angle in degrees into -180..=180.
+fn wrap_degrees(angle: f32) -> f32 {
+    (angle + 540.).rem_euclid(360.) - 180.
+}
+
+impl CGObject {
+    /// The rotation in whichever representation the object uses.
+    pub(super) fn rotation_quat(&self) -> Quat {
+        match self.rotation_mode {
+            RotationMode::AxisAngle => Quat::from_axis_angle(
+                self.axis_angle.axis.normalize_or(Vec3::Y),
+                self.axis_angle.angle.to_radians(),
+            ),
+            RotationMode::Quaternion => self.quaternion.normalize(),
+            mode => {
+                let (order, [a, b, c]) = mode.euler().unwrap();
+                let angles = self.rotation.map(f32::to_radians);
+                Quat::from_euler(order, angles[a], angles[b], angles[c])
+            }
+        }
+    }
+
+    /// Stores `rotation` in the representation the object uses.
+    pub(super) fn set_rotation_quat(&mut self, rotation: Quat) {
+        let rotation = rotation.normalize();
+        match self.rotation_mode {
+            RotationMode::AxisAngle => {
+                let (axis, angle) = rotation.to_axis_angle();
+                // Keep the old axis around for the identity rotation.
+                if angle.abs() > 1e-6 {
+                    self.axis_angle.axis = axis;
+                }
+                self.axis_angle.angle = wrap_degrees(angle.to_degrees());
+            }
+            RotationMode::Quaternion => self.quaternion = rotation,
+            mode => {
+                let (order, [a, b, c]) = mode.euler().unwrap();
+                let (first, second, third) = rotation.to_euler(order);
+                self.rotation[a] = first.to_degrees();
+                self.rotation[b] = second.to_degrees();
+                self.rotation[c] = third.to_degrees();
+            }
+        }
+    }
+
+    /// Switches to another representation, keeping the rotation the same.
+    pub(super) fn set_rotation_mode(&mut self, mode: RotationMode) {
+        let rotation = self.rotation_quat();
+        self.rotation_mode = mode;
+        self.set_rotation_quat(rotation);
+    }
+
+    /// Turns the object by `angle` degrees around `axis` in its parent's space.
+    pub(super) fn rotate(&mut self, axis: Vec3, angle: f32) {
+        let rotation = Quat::from_axis_angle(axis, angle.to_radians()) * self.rotation_quat();
+        self.set_rotation_quat(rotation);
+    }
+
+    pub(super) fn rotation_ui(&mut self, ui: &mut egui::Ui) {
+        let mut mode = self.rotation_mode;
+        egui::ComboBox::new("obj_rotation_mode", "Representation")
+            .selected_text(Into::<&'static str>::into(mode))
+            .show_ui(ui, |ui| {
+                for option in RotationMode::iter() {
+                    ui.selectable_value(&mut mode, option, Into::<&'static str>::into(option));
+                }
+            });
+        if mode != self.rotation_mode {
+            self.set_rotation_mode(mode);
+        }
+
+        match self.rotation_mode {
+            RotationMode::AxisAngle => {
+                let axis = &mut self.axis_angle.axis;
+                ui.add(Slider::new(&mut axis[0], -1.0..=1.0).text("Axis.x"));
+                ui.add(Slider::new(&mut axis[1], -1.0..=1.0).text("Axis.y"));
+                ui.add(Slider::new(&mut axis[2], -1.0..=1.0).text("Axis.z"));
+                ui.add(Slider::new(&mut self.axis_angle.angle, -180.0..=180.0).text("Angle"));
+            }
+            RotationMode::Quaternion => {
+                let quaternion = &mut self.quaternion;
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut quaternion.w).speed(0.01).prefix("w: "));
+                    ui.add(DragValue::new(&mut quaternion.x).speed(0.01).prefix("x: "));
+                    ui.add(DragValue::new(&mut quaternion.y).speed(0.01).prefix("y: "));
+                    ui.add(DragValue::new(&mut quaternion.z).speed(0.01).prefix("z: "));
+                });
+                if !quaternion.is_finite() || quaternion.length_squared() < 1e-6 {
+                    *quaternion = Quat::IDENTITY;
+                }
+                if ui.button("Normalize").clicked() {
+                    *quaternion = quaternion.normalize();
+                }
+            }
+            _ => {
+                ui.add(Slider::new(&mut self.rotation[0], -180.0..=180.0).text("Rotation.x"));
+                ui.add(Slider::new(&mut self.rotation[1], -180.0..=180.0).text("Rotation.y"));
+                ui.add(Slider::new(&mut self.rotation[2], -180.0..=180.0).text("Rotation.z"));
+            }
+        }
+
+        for angle in [90., 180.] {
+            ui.horizontal(|ui| {
+                for (i, (name, axis)) in [("X", Vec3::X), ("Y", Vec3::Y), ("Z", Vec3::Z)]
+                    .into_iter()
+                    .enumerate()
+                {
+                    if ui.button(format!("{name} +{angle}°")).clicked() {
+                        if self.rotation_mode.euler().is_some() {
+                            self.rotation[i] = wrap_degrees(self.rotation[i] + angle);
+                        } else {
+                            self.rotate(axis, angle);
+                        }
+                    }
+                }
+            });
+        }
+
+        if ui.button("Reset Rotation").clicked() {
+            self.rotation = Vec3::ZERO;
+            self.axis_angle.angle = 0.;
+            self.quaternion = Quat::IDENTITY;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    fn same_rotation(a: Quat, b: Quat) -> bool {
+        // q and -q are the same rotation.
+        a.dot(b).abs() > 1. - 1e-5
+    }
+
+    fn rotations() -> Vec<Quat> {
+        vec![
+            Quat::IDENTITY,
+            Quat::from_rotation_x(1.),
+            Quat::from_axis_angle(vec3(1., 2., 3.).normalize(), 2.5),
+            Quat::from_rotation_z(std::f32::consts::PI),
+            Quat::from_euler(EulerRot::XYZ, 0.3, -0.7, 2.9),
+        ]
+    }
+
+    /// Rotations at gimbal lock for each Euler order: the middle axis at ±90°.
+    fn gimbal_locked() -> Vec<(RotationMode, Vec3)> {
+        RotationMode::iter()
+            .filter_map(|mode| Some((mode, mode.euler()?.1)))
+            .flat_map(|(mode, [_, b, _])| {
+                [90., -90.].map(|middle| {
+                    let mut rotation = vec3(30., -40., 50.);
+                    rotation[b] = middle;
+                    (mode, rotation)
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn euler_xyz_is_x_then_y_then_z() {
+        let obj = CGObject {
+            rotation: vec3(10., 20., 30.),
+            ..Default::default()
+        };
+        let expected = Quat::from_rotation_x(10f32.to_radians())
+            * Quat::from_rotation_y(20f32.to_radians())
+            * Quat::from_rotation_z(30f32.to_radians());
+        assert!(same_rotation(obj.rotation_quat(), expected));
+    }
+
+    #[test]
+    fn every_representation_round_trips() {
+        for mode in RotationMode::iter() {
+            for rotation in rotations() {
+                let mut obj = CGObject {
+                    rotation_mode: mode,
+                    ..Default::default()
+                };
+                obj.set_rotation_quat(rotation);
+                assert!(
+                    same_rotation(obj.rotation_quat(), rotation),
+                    "{mode:?} {rotation:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn switching_representations_keeps_the_rotation() {
+        for rotation in rotations() {
+            let mut obj = CGObject::default();
+            obj.set_rotation_quat(rotation);
+            for mode in RotationMode::iter().chain([RotationMode::EulerXyz]) {
+                obj.set_rotation_mode(mode);
+                assert!(
+                    same_rotation(obj.rotation_quat(), rotation),
+                    "{mode:?} {rotation:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gimbal_lock_round_trips() {
+        for (mode, angles) in gimbal_locked() {
+            let obj = CGObject {
+                rotation_mode: mode,
+                rotation: angles,
+                ..Default::default()
+            };
+            let rotation = obj.rotation_quat();
+            // The angles that come back may differ, the rotation must not.
+            for other in RotationMode::iter() {
+                let mut copy = CGObject {
+                    rotation_mode: other,
+                    ..Default::default()
+                };
+                copy.set_rotation_quat(rotation);
+                assert!(copy.rotation.is_finite(), "{mode:?} -> {other:?}");
+                assert!(
+                    same_rotation(copy.rotation_quat(), rotation),
+                    "{mode:?} {angles} -> {other:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn axis_angle_keeps_its_axis_for_the_identity() {
+        let mut obj = CGObject {
+            rotation_mode: RotationMode::AxisAngle,
+            axis_angle: AxisAngle {
+                axis: Vec3::X,
+                angle: 0.,
+            },
+            ..Default::default()
+        };
+        obj.set_rotation_quat(Quat::IDENTITY);
+        assert_eq!(obj.axis_angle.axis, Vec3::X);
+        assert_eq!(obj.axis_angle.angle, 0.);
+    }
+
+    #[test]
+    fn rotate_composes_in_parent_space() {
+        let mut obj = CGObject::default();
+        obj.rotate(Vec3::X, 90.);
+        obj.rotate(Vec3::Y, 90.);
+        let expected =
+            Quat::from_rotation_y(90f32.to_radians()) * Quat::from_rotation_x(90f32.to_radians());
+        assert!(same_rotation(obj.rotation_quat(), expected));
+    }
+
+    #[test]
+    fn degrees_wrap() {
+        assert_eq!(wrap_degrees(190.), -170.);
+        assert_eq!(wrap_degrees(-190.), 170.);
+        assert_eq!(wrap_degrees(45.), 45.);
+    }
+}
@@ -0,0 +1,167 @@
+use glam::Mat4;
+
+use super::CGObject;
+
+/// Whether `ancestor` is `id` itself or one of its (transitive) parents.
+pub(super) fn is_ancestor(objects: &[CGObject], ancestor: usize, mut id: usize) -> bool {
+    // A cycle can't be longer than the object list, so this always ends.
+    for _ in 0..=objects.len() {
+        if id == ancestor {
+            return true;
+        }
+        match objects.get(id).and_then(|obj| obj.parent) {
+            Some(parent) => id = parent,
+            None => return false,
+        }
+    }
+    false
+}
+
+/// Detaches objects whose parent doesn't exist or that are part of a cycle,
+/// which old or hand-edited state could contain.
+pub(super) fn fix_parents(objects: &mut [CGObject]) {
+    for id in 0..objects.len() {
+        if let Some(parent) = objects[id].parent {
+            if parent >= objects.len() || is_ancestor(objects, id, parent) {
+                objects[id].parent = None;
+            }
+        }
+    }
+}
+
+/// Model to world matrix of every object, composed through the hierarchy.
+///
+/// Expects [`fix_parents`] to have been run on `objects`.
+pub(super) fn world_matrices(objects: &[CGObject]) -> Vec<Mat4> {
+    let mut world: Vec<Option<Mat4>> = vec![None; objects.len()];
+    for id in 0..objects.len() {
+        if world[id].is_some() {
+            continue;
+        }
+        // Walk up to the closest ancestor we already know, then back down.
+        let mut chain = vec![id];
+        while let Some(parent) = objects[*chain.last().unwrap()].parent {
+            if world[parent].is_some() {
+                break;
+            }
+            chain.push(parent);
+        }
+        for &id in chain.iter().rev() {
+            let parent = objects[id]
+                .parent
+                .and_then(|parent| world[parent])
+                .unwrap_or(Mat4::IDENTITY);
+            world[id] = Some(parent * objects[id].mv_matrix());
+        }
+    }
+    world.into_iter().map(Option::unwrap).collect()
+}
+
+/// The children of `id`, or the root objects for `None`.
+pub(super) fn children(objects: &[CGObject], id: Option<usize>) -> Vec<usize> {
+    (0..objects.len())
+        .filter(|&child| objects[child].parent == id)
+        .collect()
+}
+
+/// Moves `id` under `parent` (or to the root), adjusting its local transform
+/// so it stays where it is in the world. Refuses, returning false, when that
+/// would create a cycle.
+///
+/// Shear and non-uniform scale in the parents can produce world transforms
+/// the local translation, rotation and scale can't express, those come out
+/// as close as possible.
+pub(super) fn reparent(objects: &mut [CGObject], id: usize, parent: Option<usize>) -> bool {
+    if parent.is_some_and(|parent| is_ancestor(objects, id, parent)) {
+        return false;
+    }
+    let world = world_matrices(objects);
+    let parent_world = parent.map_or(Mat4::IDENTITY, |parent| world[parent]);
+    let local = parent_world.inverse() * world[id];
+
+    let obj = &mut objects[id];
+    // Take the object's own pivot and shear back out, they stay as they are.
+    let (scale, rotation, translation) =
+        (local * Mat4::from_translation(obj.pivot) * obj.shear.matrix().inverse())
+            .to_scale_rotation_translation();
+    if scale.is_finite() && rotation.is_finite() && translation.is_finite() {
+        obj.scale = scale;
+        obj.set_rotation_quat(rotation);
+        obj.translation = translation - obj.pivot;
+    }
+    obj.parent = parent;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec3, Vec3};
+
+    use super::*;
+
+    /// A chain 0 <- 1 <- 2, plus 3 on its own, each moved a bit.
+    fn objects() -> Vec<CGObject> {
+        let mut objects: Vec<_> = (0..4)
+            .map(|i| CGObject {
+                translation: vec3(i as f32, 0., 0.),
+                ..Default::default()
+            })
+            .collect();
+        objects[1].parent = Some(0);
+        objects[2].parent = Some(1);
+        objects
+    }
+
+    fn parents(objects: &[CGObject]) -> Vec<Option<usize>> {
+        objects.iter().map(|obj| obj.parent).collect()
+    }
+
+    #[test]
+    fn ancestors() {
+        let objects = objects();
+        assert!(is_ancestor(&objects, 0, 2));
+        assert!(is_ancestor(&objects, 2, 2));
+        assert!(!is_ancestor(&objects, 2, 0));
+        assert!(!is_ancestor(&objects, 3, 2));
+    }
+
+    #[test]
+    fn reparenting_under_a_descendant_is_refused() {
+        let mut objects = objects();
+        let before = parents(&objects);
+        assert!(!reparent(&mut objects, 0, Some(2)));
+        assert!(!reparent(&mut objects, 0, Some(1)));
+        assert!(!reparent(&mut objects, 1, Some(1)));
+        assert_eq!(parents(&objects), before);
+    }
+
+    #[test]
+    fn reparenting_keeps_the_world_transform() {
+        let mut objects = objects();
+        objects[3].rotation = vec3(0., 30., 0.);
+        objects[3].scale = Vec3::splat(2.);
+        let before = world_matrices(&objects)[2];
+        assert!(reparent(&mut objects, 2, Some(3)));
+        assert_eq!(objects[2].parent, Some(3));
+        assert!(world_matrices(&objects)[2].abs_diff_eq(before, 1e-4));
+        assert!(reparent(&mut objects, 2, None));
+        assert!(world_matrices(&objects)[2].abs_diff_eq(before, 1e-4));
+    }
+
+    #[test]
+    fn cycles_and_dangling_parents_are_detached() {
+        let mut objects = objects();
+        objects[0].parent = Some(2);
+        objects[3].parent = Some(9);
+        fix_parents(&mut objects);
+        assert_eq!(objects[3].parent, None);
+        // Breaking one link is enough to end the cycle.
+        assert!(parents(&objects).contains(&None));
+        for id in 0..objects.len() {
+            if let Some(parent) = objects[id].parent {
+                assert!(!is_ancestor(&objects, id, parent));
+            }
+        }
+        assert_eq!(world_matrices(&objects).len(), 4);
+    }
+}
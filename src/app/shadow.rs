@@ -0,0 +1,317 @@
+use eframe::{egui_glow::ShaderVersion, glow};
+use glam::{Mat4, Vec4};
+use glow::HasContext as _;
+
+use super::{
+    clipping,
+    light::{shadow_slots, Light, ShadowSlot, MAX_SHADOW_MAPS, SHADOW_FAR, SHADOW_NEAR},
+    GLStuff, ICGLoaded,
+};
+
+/// Texture unit of the first 2D shadow map, the cube maps follow right after
+/// them. Unit 0 is left to egui.
+const FIRST_SHADOW_UNIT: u32 = 1;
+
+/// Definitions the main fragment shader needs before `lighting.glsl` to sample
+/// the shadow maps.
+pub(super) fn shader_defines() -> String {
+    format!(
+        "#define SHADOWS\n#define MAX_SHADOW_MAPS {MAX_SHADOW_MAPS}\n#define SHADOW_NEAR {SHADOW_NEAR:?}\n#define SHADOW_FAR {SHADOW_FAR:?}\n"
+    )
+}
+
+/// The shadow map samplers and `shadow.glsl`. Sampler arrays can only be
+/// indexed by constants, so the lookups by slot are unrolled here for every
+/// one of the [`MAX_SHADOW_MAPS`] slots.
+pub(super) fn shader_source() -> String {
+    let chain = |sampler: &str| {
+        let mut chain = String::new();
+        for slot in 0..MAX_SHADOW_MAPS {
+            chain += &format!("    if(slot == {slot}) return texture({sampler}[{slot}], coord);\n");
+        }
+        chain
+    };
+    format!(
+        "uniform highp sampler2DShadow shadowMaps[MAX_SHADOW_MAPS];\n\
+         uniform highp samplerCubeShadow shadowCubes[MAX_SHADOW_MAPS];\n\n\
+         float sampleMap(int slot, vec3 coord) {{\n{}    return 1.;\n}}\n\n\
+         float sampleCube(int slot, vec4 coord) {{\n{}    return 1.;\n}}\n\n{}",
+        chain("shadowMaps"),
+        chain("shadowCubes"),
+        include_str!("../shadow.glsl")
+    )
+}
+
+struct DepthTexture {
+    texture: glow::Texture,
+    resolution: u32,
+}
+
+/// Depth textures for the lights that cast shadows, along with the depth-only
+/// program that renders them.
+pub(super) struct ShadowMaps {
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+    framebuffer: glow::Framebuffer,
+    maps: Vec<DepthTexture>,
+    cubes: Vec<DepthTexture>,
+}
+
+#[allow(unsafe_code)] // we need unsafe code to use glow
+impl ShadowMaps {
+    pub(super) fn new(shader_version: ShaderVersion, gl: &glow::Context) -> Self {
+        unsafe {
+            let program = GLStuff::create_program(
+                shader_version,
+                include_str!("../shadow_vertex.glsl"),
+                &format!(
+                    "{}\n{}",
+                    clipping::shader_source(),
+                    include_str!("../shadow_fragment.glsl")
+                ),
+                gl,
+            );
+
+            let vertex_array = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vertex_array));
+            let vertex_position_loc = gl.get_attrib_location(program, "aVertexPosition").unwrap();
+            gl.enable_vertex_attrib_array(vertex_position_loc);
+            gl.bind_vertex_array(None);
+
+            let framebuffer = gl.create_framebuffer().unwrap();
+
+            // Every sampler has to see a complete texture of its own type, so
+            // unused slots keep a 1x1 texture around.
+            let new_texture = |target| {
+                let texture = gl.create_texture().unwrap();
+                gl.bind_texture(target, Some(texture));
+                for (parameter, value) in [
+                    (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+                    (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                    (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                    (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+                    (glow::TEXTURE_WRAP_R, glow::CLAMP_TO_EDGE),
+                    (glow::TEXTURE_COMPARE_MODE, glow::COMPARE_REF_TO_TEXTURE),
+                    (glow::TEXTURE_COMPARE_FUNC, glow::LEQUAL),
+                ] {
+                    gl.tex_parameter_i32(target, parameter, value as i32);
+                }
+                gl.bind_texture(target, None);
+                let mut texture = DepthTexture {
+                    texture,
+                    resolution: 0,
+                };
+                texture.resize(gl, target, 1);
+                texture
+            };
+            let maps = (0..MAX_SHADOW_MAPS)
+                .map(|_| new_texture(glow::TEXTURE_2D))
+                .collect();
+            let cubes = (0..MAX_SHADOW_MAPS)
+                .map(|_| new_texture(glow::TEXTURE_CUBE_MAP))
+                .collect();
+
+            Self {
+                program,
+                vertex_array,
+                framebuffer,
+                maps,
+                cubes,
+            }
+        }
+    }
+
+    pub(super) fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.vertex_array);
+            gl.delete_framebuffer(self.framebuffer);
+            for texture in self.maps.iter().chain(&self.cubes) {
+                gl.delete_texture(texture.texture);
+            }
+        }
+    }
+
+    /// Resizes the textures to the resolution of the lights using them.
+    pub(super) fn prepare(&mut self, gl: &glow::Context, lights: &[Light]) {
+        for (light, slot) in lights.iter().zip(shadow_slots(lights)) {
+            match slot {
+                Some(ShadowSlot::Map(i)) => {
+                    self.maps[i].resize(gl, glow::TEXTURE_2D, light.shadow_resolution)
+                }
+                Some(ShadowSlot::Cube(i)) => {
+                    self.cubes[i].resize(gl, glow::TEXTURE_CUBE_MAP, light.shadow_resolution)
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Renders the depth of `objects` (model matrix, mesh and clipping planes)
+    /// from every light
+    /// that casts shadows. Leaves `intermediate_fbo` bound and the viewport
+    /// and scissor test as they were.
+    pub(super) fn render(
+        &self,
+        gl: &glow::Context,
+        lights: &[Light],
+        objects: &[(Mat4, &ICGLoaded, &[Vec4])],
+        intermediate_fbo: Option<glow::Framebuffer>,
+    ) {
+        let slots = shadow_slots(lights);
+        if slots.iter().all(Option::is_none) {
+            return;
+        }
+
+        unsafe {
+            let mut viewport = [0; 4];
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+            gl.disable(glow::SCISSOR_TEST);
+
+            gl.use_program(Some(self.program));
+            gl.enable(glow::DEPTH_TEST);
+            let light_mat_loc = gl.get_uniform_location(self.program, "uLightMatrix");
+            let mv_mat_loc = gl.get_uniform_location(self.program, "uMVMatrix");
+            let vertex_position_loc = gl
+                .get_attrib_location(self.program, "aVertexPosition")
+                .unwrap();
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            gl.draw_buffers(&[glow::NONE]);
+            gl.read_buffer(glow::NONE);
+            gl.bind_vertex_array(Some(self.vertex_array));
+
+            let draw = |texture_target, texture: &DepthTexture, light_mat: Mat4| {
+                gl.framebuffer_texture_2d(
+                    glow::FRAMEBUFFER,
+                    glow::DEPTH_ATTACHMENT,
+                    texture_target,
+                    Some(texture.texture),
+                    0,
+                );
+                let resolution = texture.resolution as i32;
+                gl.viewport(0, 0, resolution, resolution);
+                gl.clear(glow::DEPTH_BUFFER_BIT);
+                gl.uniform_matrix_4_f32_slice(
+                    light_mat_loc.as_ref(),
+                    false,
+                    &light_mat.to_cols_array(),
+                );
+                for (mv_mat, model, clipping_planes) in objects {
+                    clipping::set_uniforms(gl, self.program, clipping_planes);
+                    gl.uniform_matrix_4_f32_slice(
+                        mv_mat_loc.as_ref(),
+                        false,
+                        &mv_mat.to_cols_array(),
+                    );
+                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(model.pos_buffer));
+                    gl.vertex_attrib_pointer_f32(vertex_position_loc, 3, glow::FLOAT, false, 0, 0);
+                    gl.draw_arrays(glow::TRIANGLES, 0, model.item_count);
+                }
+            };
+
+            for (light, slot) in lights.iter().zip(slots) {
+                match slot {
+                    Some(ShadowSlot::Map(i)) => {
+                        draw(glow::TEXTURE_2D, &self.maps[i], light.shadow_matrix())
+                    }
+                    Some(ShadowSlot::Cube(i)) => {
+                        for (face, light_mat) in
+                            light.cube_shadow_matrices().into_iter().enumerate()
+                        {
+                            draw(
+                                glow::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                                &self.cubes[i],
+                                light_mat,
+                            );
+                        }
+                    }
+                    None => {}
+                }
+            }
+
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_ATTACHMENT,
+                glow::TEXTURE_2D,
+                None,
+                0,
+            );
+            gl.bind_vertex_array(None);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, intermediate_fbo);
+            gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            if scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+        }
+    }
+
+    /// Binds the shadow maps for `program`, which has to be the current one.
+    pub(super) fn bind(&self, gl: &glow::Context, program: glow::Program) {
+        let units = (FIRST_SHADOW_UNIT..).map(|unit| (glow::TEXTURE0 + unit, unit as i32));
+        let textures = self
+            .maps
+            .iter()
+            .enumerate()
+            .map(|(i, texture)| (glow::TEXTURE_2D, format!("shadowMaps[{i}]"), texture))
+            .chain(self.cubes.iter().enumerate().map(|(i, texture)| {
+                (glow::TEXTURE_CUBE_MAP, format!("shadowCubes[{i}]"), texture)
+            }));
+        unsafe {
+            for ((target, name, texture), (unit, index)) in textures.zip(units) {
+                gl.active_texture(unit);
+                gl.bind_texture(target, Some(texture.texture));
+                gl.uniform_1_i32(gl.get_uniform_location(program, &name).as_ref(), index);
+            }
+            gl.active_texture(glow::TEXTURE0);
+        }
+    }
+}
+
+#[allow(unsafe_code)] // we need unsafe code to use glow
+impl DepthTexture {
+    fn resize(&mut self, gl: &glow::Context, target: u32, resolution: u32) {
+        if self.resolution == resolution {
+            return;
+        }
+        self.resolution = resolution;
+        let faces = if target == glow::TEXTURE_CUBE_MAP {
+            (glow::TEXTURE_CUBE_MAP_POSITIVE_X..glow::TEXTURE_CUBE_MAP_POSITIVE_X + 6).collect()
+        } else {
+            vec![target]
+        };
+        unsafe {
+            gl.bind_texture(target, Some(self.texture));
+            for face in faces {
+                gl.tex_image_2d(
+                    face,
+                    0,
+                    glow::DEPTH_COMPONENT24 as i32,
+                    resolution as i32,
+                    resolution as i32,
+                    0,
+                    glow::DEPTH_COMPONENT,
+                    glow::UNSIGNED_INT,
+                    glow::PixelUnpackData::Slice(None),
+                );
+            }
+            gl.bind_texture(target, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_slot_is_sampled() {
+        let source = shader_source();
+        for slot in 0..MAX_SHADOW_MAPS {
+            assert!(source.contains(&format!("texture(shadowMaps[{slot}], coord)")));
+            assert!(source.contains(&format!("texture(shadowCubes[{slot}], coord)")));
+        }
+        assert!(!source.contains(&format!("[{MAX_SHADOW_MAPS}]")));
+    }
+}
@@ -0,0 +1,254 @@
+use egui::{RichText, Slider};
+use glam::{Mat3, Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+use super::{scene, viewport::Pane, CGObject, DemoApp};
+
+/// Shear matrices closer to singular than this are refused, they would
+/// flatten the object and break its normals.
+const MIN_SHEAR_DETERMINANT: f32 = 0.05;
+
+/// The six off-diagonal entries of a shear matrix, `xy` is how much x grows
+/// with y and so on.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Shear {
+    pub(super) xy: f32,
+    pub(super) xz: f32,
+    pub(super) yx: f32,
+    pub(super) yz: f32,
+    pub(super) zx: f32,
+    pub(super) zy: f32,
+}
+
+impl Shear {
+    /// Converts the three shear angles (in degrees, 90 for none) objects used
+    /// to have.
+    pub(super) fn from_angles(angles: Vec3) -> Self {
+        let factor = |angle: f32| {
+            let factor = angle.to_radians().tan().recip();
+            if factor.is_finite() {
+                factor
+            } else {
+                0.
+            }
+        };
+        let shear = Self {
+            xy: factor(angles.x),
+            yz: factor(angles.y),
+            zx: factor(angles.z),
+            ..Default::default()
+        };
+        if shear.is_safe() {
+            shear
+        } else {
+            Default::default()
+        }
+    }
+
+    fn mat3(&self) -> Mat3 {
+        Mat3::from_cols(
+            Vec3::new(1., self.yx, self.zx),
+            Vec3::new(self.xy, 1., self.zy),
+            Vec3::new(self.xz, self.yz, 1.),
+        )
+    }
+
+    pub(super) fn matrix(&self) -> Mat4 {
+        Mat4::from_mat3(self.mat3())
+    }
+
+    fn determinant(&self) -> f32 {
+        self.mat3().determinant()
+    }
+
+    /// Whether the matrix is far enough from singular to keep, flipping
+    /// (negative determinants) isn't allowed either.
+    fn is_safe(&self) -> bool {
+        self.determinant() >= MIN_SHEAR_DETERMINANT
+    }
+
+    pub(super) fn ui(&mut self, ui: &mut egui::Ui) {
+        let old = *self;
+        for (value, name) in [
+            (&mut self.xy, "x by y"),
+            (&mut self.xz, "x by z"),
+            (&mut self.yx, "y by x"),
+            (&mut self.yz, "y by z"),
+            (&mut self.zx, "z by x"),
+            (&mut self.zy, "z by y"),
+        ] {
+            ui.add(Slider::new(value, -2.0..=2.0).text(name));
+        }
+        if !self.is_safe() {
+            *self = old;
+            ui.label(
+                RichText::new("That would make the matrix (nearly) singular")
+                    .color(ui.visuals().error_fg_color),
+            );
+        }
+    }
+}
+
+impl CGObject {
+    /// Rotation, scale and shear, the part of the model matrix that happens
+    /// around the pivot.
+    pub(super) fn linear_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation_quat(), Vec3::ZERO)
+            * self.shear.matrix()
+    }
+
+    /// Moves the pivot, shifting the translation so the object stays where
+    /// it is.
+    pub(super) fn set_pivot(&mut self, pivot: Vec3) {
+        let offset = self.pivot - pivot;
+        self.translation += offset - self.linear_matrix().transform_vector3(offset);
+        self.pivot = pivot;
+    }
+
+    /// `bounds` is the model's bounding box, if it is known.
+    pub(super) fn pivot_ui(&mut self, ui: &mut egui::Ui, bounds: Option<[Vec3; 2]>) {
+        let mut pivot = self.pivot;
+        ui.add(Slider::new(&mut pivot[0], -20.0..=20.0).text("Pivot.x"));
+        ui.add(Slider::new(&mut pivot[1], -20.0..=20.0).text("Pivot.y"));
+        ui.add(Slider::new(&mut pivot[2], -20.0..=20.0).text("Pivot.z"));
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(bounds.is_some(), egui::Button::new("Center on Bounds"))
+                .clicked()
+            {
+                if let Some([min, max]) = bounds {
+                    pivot = (min + max) / 2.;
+                }
+            }
+            if ui.button("Reset Pivot").clicked() {
+                pivot = Vec3::ZERO;
+            }
+        });
+        if pivot != self.pivot {
+            self.set_pivot(pivot);
+        }
+    }
+}
+
+/// Shows `matrix` as a 4x4 grid.
+fn matrix_grid(ui: &mut egui::Ui, name: &str, matrix: Mat4) {
+    ui.label(name);
+    egui::Grid::new(name).striped(true).show(ui, |ui| {
+        for row in 0..4 {
+            for value in matrix.row(row).to_array() {
+                ui.monospace(format!("{value:>8.3}"));
+            }
+            ui.end_row();
+        }
+    });
+}
+
+impl DemoApp {
+    /// Every matrix between the selected object's vertices and clip space,
+    /// as seen through the main camera.
+    pub(super) fn pipeline_inspector(&self, ui: &mut egui::Ui) {
+        let Some(id) = self.selected_object.filter(|&id| id < self.objects.len()) else {
+            ui.label("None Selected");
+            return;
+        };
+        let obj = &self.objects[id];
+        let world = scene::world_matrices(&self.objects);
+
+        ui.label("Model = Translation * Pivot * Rotation * Scale * Shear * Pivot⁻¹");
+        matrix_grid(ui, "Pivot", Mat4::from_translation(obj.pivot));
+        matrix_grid(ui, "Scale", Mat4::from_scale(obj.scale));
+        matrix_grid(ui, "Shear", obj.shear.matrix());
+        matrix_grid(ui, "Rotation", Mat4::from_quat(obj.rotation_quat()));
+        matrix_grid(ui, "Translation", Mat4::from_translation(obj.translation));
+        matrix_grid(ui, "Model", obj.mv_matrix());
+        if let Some(parent) = obj.parent {
+            matrix_grid(ui, "Parent (world)", world[parent]);
+            matrix_grid(ui, "World = Parent * Model", world[id]);
+        }
+
+        let scene_data = self.get_scene_data(&Pane {
+            main_camera: true,
+            ..Default::default()
+        });
+        let view = scene_data.view_matrix();
+        matrix_grid(ui, "View", view);
+        let Some(projection) = self
+            .gl_stuff
+            .lock()
+            .as_ref()
+            .map(|stuff| stuff.projection_matrix(&scene_data, self.main_aspect))
+        else {
+            return;
+        };
+        matrix_grid(ui, "Projection", projection);
+        matrix_grid(
+            ui,
+            "Projection * View * World",
+            projection * view * world[id],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    #[test]
+    fn right_angles_are_no_shear() {
+        let matrix = Shear::from_angles(Vec3::splat(90.)).matrix();
+        assert!(matrix.abs_diff_eq(Mat4::IDENTITY, 1e-6), "{matrix}");
+    }
+
+    #[test]
+    fn extreme_angles_never_make_a_singular_matrix() {
+        let extremes = [
+            0.,
+            1e-4,
+            0.5,
+            1.,
+            179.,
+            179.5,
+            180. - 1e-4,
+            180.,
+            -180.,
+            360.,
+        ];
+        for &x in &extremes {
+            for &y in &extremes {
+                for &z in &extremes {
+                    for angles in [vec3(x, y, z), vec3(x, 90., 90.), vec3(90., y, z)] {
+                        let shear = Shear::from_angles(angles);
+                        let matrix = shear.matrix();
+                        assert!(matrix.is_finite(), "{angles}");
+                        assert!(
+                            matrix.determinant() >= MIN_SHEAR_DETERMINANT,
+                            "{angles} gave {shear:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsafe_factors_are_caught() {
+        // The determinant of the cyclic shear is 1 + xy * yz * zx.
+        let shear = Shear {
+            xy: -1.,
+            yz: 1.,
+            zx: 0.99,
+            ..Default::default()
+        };
+        assert!(!shear.is_safe());
+        assert!(Shear { zx: -0.9, ..shear }.is_safe());
+        assert!(!Shear {
+            xy: 1.,
+            yx: 1.,
+            ..Default::default()
+        }
+        .is_safe());
+    }
+}
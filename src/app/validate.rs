@@ -0,0 +1,438 @@
+use std::fmt;
+
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use super::{obj::generate_normals, ICGJson};
+
+/// Color used to pad models that come with too few vertex colors.
+const PAD_COLOR: [f32; 3] = [0.8, 0.8, 0.8];
+
+/// Triangles with less area than this are considered degenerate.
+const DEGENERATE_AREA: f32 = 1e-12;
+
+/// Which problems found by [`ICGJson::validate`] get fixed before uploading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(super) struct RepairOptions {
+    /// Pad (or truncate) the color arrays to match the positions.
+    pub(super) pad_colors: bool,
+    /// Replace missing or mismatched normals with face normals, or only the
+    /// broken ones if the count is right.
+    pub(super) recompute_normals: bool,
+    /// Remove triangles with non-finite positions or zero area.
+    pub(super) drop_bad_triangles: bool,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self {
+            pad_colors: true,
+            recompute_normals: true,
+            drop_bad_triangles: true,
+        }
+    }
+}
+
+/// Everything [`ICGJson::validate`] found wrong with a mesh.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(super) struct ValidationReport {
+    /// Number of position values.
+    pub(super) position_values: usize,
+    /// Position values left over after the last whole triangle.
+    pub(super) trailing_position_values: usize,
+    /// Number of normal values, if it differs from the position count.
+    pub(super) normal_mismatch: Option<usize>,
+    /// Number of front color values, if it differs from the position count.
+    pub(super) color_mismatch: Option<usize>,
+    /// Number of back color values, if it differs from the position count.
+    pub(super) back_color_mismatch: Option<usize>,
+    /// Triangles with a NaN or infinite position.
+    pub(super) non_finite_triangles: Vec<usize>,
+    /// Vertices with a NaN, infinite or zero-length normal.
+    pub(super) bad_normals: Vec<usize>,
+    /// Vertices with a NaN or infinite color.
+    pub(super) non_finite_colors: Vec<usize>,
+    /// Triangles with (nearly) zero area.
+    pub(super) degenerate_triangles: Vec<usize>,
+}
+
+impl ValidationReport {
+    pub(super) fn is_clean(&self) -> bool {
+        *self
+            == Self {
+                position_values: self.position_values,
+                ..Default::default()
+            }
+    }
+
+    /// Whether uploading the mesh as is would read out of bounds or feed
+    /// garbage to the shaders. Degenerate triangles and broken normals only
+    /// look wrong, so they aren't fatal, and back colors are never uploaded.
+    pub(super) fn is_fatal(&self) -> bool {
+        self.trailing_position_values != 0
+            || self.normal_mismatch.is_some()
+            || self.color_mismatch.is_some()
+            || !self.non_finite_triangles.is_empty()
+            || !self.non_finite_colors.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let positions = self.position_values;
+        let mut problems = Vec::new();
+        if self.trailing_position_values != 0 {
+            problems.push(format!(
+                "{} position values is not a whole number of triangles",
+                positions
+            ));
+        }
+        if let Some(len) = self.normal_mismatch {
+            problems.push(format!("{len} normal values for {positions} positions"));
+        }
+        if let Some(len) = self.color_mismatch {
+            problems.push(format!("{len} color values for {positions} positions"));
+        }
+        if let Some(len) = self.back_color_mismatch {
+            problems.push(format!("{len} back color values for {positions} positions"));
+        }
+        for (list, what) in [
+            (
+                &self.non_finite_triangles,
+                "triangles with NaN/Inf positions",
+            ),
+            (&self.bad_normals, "NaN/Inf or zero-length normals"),
+            (&self.non_finite_colors, "NaN/Inf colors"),
+            (&self.degenerate_triangles, "degenerate triangles"),
+        ] {
+            if !list.is_empty() {
+                problems.push(format!("{} {what}", list.len()));
+            }
+        }
+        if problems.is_empty() {
+            write!(f, "no problems")
+        } else {
+            write!(f, "{}", problems.join(", "))
+        }
+    }
+}
+
+fn is_finite(values: &[f32]) -> bool {
+    values.iter().all(|v| v.is_finite())
+}
+
+/// NaN, infinite or zero-length.
+fn is_bad_normal(normal: &[f32]) -> bool {
+    !is_finite(normal) || Vec3::from_slice(normal).length_squared() == 0.
+}
+
+/// Removes the triangles listed in (sorted) `bad` from a flat per-vertex array
+/// holding `stride` values per triangle.
+fn drop_triangles(values: &mut Vec<f32>, bad: &[usize], stride: usize) {
+    let mut bad = bad.iter().peekable();
+    *values = values
+        .chunks(stride)
+        .enumerate()
+        .filter(|(tri, _)| bad.next_if_eq(&tri).is_none())
+        .flat_map(|(_, values)| values)
+        .copied()
+        .collect();
+}
+
+impl ICGJson {
+    /// Checks the mesh arrays against each other and for values that can't be
+    /// rendered. Doesn't touch the GPU.
+    pub(super) fn validate(&self) -> ValidationReport {
+        let positions = &self.vertex_positions;
+        let mut report = ValidationReport {
+            position_values: positions.len(),
+            trailing_position_values: positions.len() % 9,
+            ..Default::default()
+        };
+        if self.vertex_normals.len() != positions.len() {
+            report.normal_mismatch = Some(self.vertex_normals.len());
+        }
+        if self.vertex_frontcolors.len() != positions.len() {
+            report.color_mismatch = Some(self.vertex_frontcolors.len());
+        }
+        if self.vertex_backcolors.len() != positions.len() {
+            report.back_color_mismatch = Some(self.vertex_backcolors.len());
+        }
+
+        for (tri, values) in positions.chunks_exact(9).enumerate() {
+            if !is_finite(values) {
+                report.non_finite_triangles.push(tri);
+                continue;
+            }
+            let a = Vec3::from_slice(&values[0..3]);
+            let b = Vec3::from_slice(&values[3..6]);
+            let c = Vec3::from_slice(&values[6..9]);
+            if (b - a).cross(c - a).length_squared() <= DEGENERATE_AREA {
+                report.degenerate_triangles.push(tri);
+            }
+        }
+        for (vertex, normal) in self.vertex_normals.chunks_exact(3).enumerate() {
+            if is_bad_normal(normal) {
+                report.bad_normals.push(vertex);
+            }
+        }
+        for (vertex, color) in self.vertex_frontcolors.chunks_exact(3).enumerate() {
+            if !is_finite(color) {
+                report.non_finite_colors.push(vertex);
+            }
+        }
+
+        report
+    }
+
+    /// Fixes the problems in `report` that `options` allows fixing.
+    ///
+    /// `report` has to come from [`ICGJson::validate`] on this very mesh.
+    pub(super) fn repair(&mut self, report: &ValidationReport, options: RepairOptions) {
+        if options.drop_bad_triangles {
+            let whole = self.vertex_positions.len() - report.trailing_position_values;
+            let mut bad = report.non_finite_triangles.clone();
+            bad.extend(&report.degenerate_triangles);
+            bad.sort_unstable();
+
+            for values in [
+                &mut self.vertex_positions,
+                &mut self.vertex_normals,
+                &mut self.vertex_frontcolors,
+                &mut self.vertex_backcolors,
+            ] {
+                values.truncate(whole);
+                drop_triangles(values, &bad, 9);
+            }
+            self.vertex_texture_coords.truncate(whole / 3 * 2);
+            drop_triangles(&mut self.vertex_texture_coords, &bad, 6);
+        }
+
+        let positions = self.vertex_positions.len();
+        if options.pad_colors {
+            for colors in [&mut self.vertex_frontcolors, &mut self.vertex_backcolors] {
+                for value in colors.iter_mut().filter(|v| !v.is_finite()) {
+                    *value = 0.;
+                }
+                colors.truncate(positions);
+                while colors.len() < positions {
+                    colors.push(PAD_COLOR[colors.len() % 3]);
+                }
+            }
+        }
+
+        if options.recompute_normals {
+            let indices: Vec<u32> = (0..positions as u32 / 3).collect();
+            if self.vertex_normals.len() != positions {
+                self.vertex_normals = generate_normals(&self.vertex_positions, &indices);
+            } else {
+                // Dropping triangles moved the vertices, so look again
+                // instead of using the report.
+                let bad: Vec<_> = self
+                    .vertex_normals
+                    .chunks_exact(3)
+                    .enumerate()
+                    .filter(|(_, normal)| is_bad_normal(normal))
+                    .map(|(vertex, _)| vertex)
+                    .collect();
+                if !bad.is_empty() {
+                    let generated = generate_normals(&self.vertex_positions, &indices);
+                    for vertex in bad {
+                        self.vertex_normals[3 * vertex..3 * vertex + 3]
+                            .copy_from_slice(&generated[3 * vertex..3 * vertex + 3]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: RepairOptions = RepairOptions {
+        pad_colors: false,
+        recompute_normals: false,
+        drop_bad_triangles: false,
+    };
+
+    /// Two triangles in the z = 0 plane with normals and colors that match.
+    fn mesh() -> ICGJson {
+        let vertex_positions = vec![
+            0., 0., 0., 1., 0., 0., 0., 1., 0., //
+            1., 0., 0., 1., 1., 0., 0., 1., 0.,
+        ];
+        let normals: Vec<f32> = [0., 0., 1.].repeat(6);
+        let colors: Vec<f32> = [1., 0., 0.].repeat(6);
+        ICGJson {
+            vertex_positions,
+            vertex_normals: normals,
+            vertex_frontcolors: colors.clone(),
+            vertex_backcolors: colors,
+            vertex_texture_coords: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn clean_mesh() {
+        let report = mesh().validate();
+        assert!(report.is_clean());
+        assert!(!report.is_fatal());
+        assert_eq!(report.to_string(), "no problems");
+    }
+
+    #[test]
+    fn length_mismatches() {
+        let mut json = mesh();
+        json.vertex_normals.truncate(9);
+        json.vertex_frontcolors.push(1.);
+        json.vertex_backcolors.clear();
+        let report = json.validate();
+        assert_eq!(report.normal_mismatch, Some(9));
+        assert_eq!(report.color_mismatch, Some(19));
+        assert_eq!(report.back_color_mismatch, Some(0));
+        assert!(report.is_fatal());
+    }
+
+    #[test]
+    fn back_colors_alone_are_not_fatal() {
+        let mut json = mesh();
+        json.vertex_backcolors.clear();
+        let report = json.validate();
+        assert!(!report.is_clean());
+        assert!(!report.is_fatal());
+    }
+
+    #[test]
+    fn trailing_positions() {
+        for extra in [1, 3, 6] {
+            let mut json = mesh();
+            json.vertex_positions
+                .extend(std::iter::repeat(0.).take(extra));
+            let report = json.validate();
+            assert_eq!(report.trailing_position_values, extra);
+            assert!(report.is_fatal(), "{extra} trailing values");
+        }
+    }
+
+    #[test]
+    fn non_finite_values() {
+        let mut json = mesh();
+        json.vertex_positions[10] = f32::NAN;
+        json.vertex_normals[0] = f32::INFINITY;
+        json.vertex_frontcolors[5] = f32::NEG_INFINITY;
+        let report = json.validate();
+        assert_eq!(report.non_finite_triangles, vec![1]);
+        assert_eq!(report.bad_normals, vec![0]);
+        assert_eq!(report.non_finite_colors, vec![1]);
+        assert!(report.degenerate_triangles.is_empty());
+        assert!(report.is_fatal());
+    }
+
+    #[test]
+    fn degenerate_triangles() {
+        let mut json = mesh();
+        // Squash the second triangle onto a line.
+        json.vertex_positions[12..15].copy_from_slice(&[0.5, 0.5, 0.]);
+        let report = json.validate();
+        assert_eq!(report.degenerate_triangles, vec![1]);
+        assert!(!report.is_fatal());
+    }
+
+    #[test]
+    fn repair_drops_bad_triangles_and_trailing_values() {
+        let mut json = mesh();
+        json.vertex_positions[0] = f32::NAN;
+        json.vertex_positions.extend([0., 0., 0., 1.]);
+        let report = json.validate();
+        json.repair(
+            &report,
+            RepairOptions {
+                drop_bad_triangles: true,
+                ..NONE
+            },
+        );
+        assert_eq!(json.vertex_positions, mesh().vertex_positions[9..]);
+        assert_eq!(json.vertex_normals.len(), 9);
+        assert_eq!(json.vertex_backcolors.len(), 9);
+        assert!(json.validate().is_clean());
+    }
+
+    #[test]
+    fn repair_without_dropping_keeps_trailing_values_fatal() {
+        let mut json = mesh();
+        json.vertex_positions.extend([0., 0., 0.]);
+        let report = json.validate();
+        json.repair(&report, NONE);
+        assert!(json.validate().is_fatal());
+    }
+
+    #[test]
+    fn repair_pads_and_fixes_colors() {
+        let mut json = mesh();
+        json.vertex_frontcolors.truncate(3);
+        json.vertex_frontcolors[1] = f32::NAN;
+        json.vertex_backcolors.extend([1., 1., 1.]);
+        let report = json.validate();
+        json.repair(
+            &report,
+            RepairOptions {
+                pad_colors: true,
+                ..NONE
+            },
+        );
+        assert_eq!(json.vertex_frontcolors.len(), 18);
+        assert_eq!(json.vertex_frontcolors[..6], [1., 0., 0., 0.8, 0.8, 0.8]);
+        assert_eq!(json.vertex_backcolors, mesh().vertex_backcolors);
+        assert!(json.validate().is_clean());
+    }
+
+    #[test]
+    fn repair_replaces_only_bad_normals() {
+        let mut json = mesh();
+        // A deliberately tilted normal that is fine and one that is broken.
+        json.vertex_normals[0..3].copy_from_slice(&[0., 1., 0.]);
+        json.vertex_normals[3..6].copy_from_slice(&[0., 0., 0.]);
+        let report = json.validate();
+        assert_eq!(report.bad_normals, vec![1]);
+        json.repair(
+            &report,
+            RepairOptions {
+                recompute_normals: true,
+                ..NONE
+            },
+        );
+        assert_eq!(json.vertex_normals[0..3], [0., 1., 0.]);
+        assert_eq!(json.vertex_normals[3..6], [0., 0., 1.]);
+        assert!(json.validate().is_clean());
+    }
+
+    #[test]
+    fn repair_regenerates_missing_normals() {
+        let mut json = mesh();
+        json.vertex_normals.clear();
+        let report = json.validate();
+        json.repair(
+            &report,
+            RepairOptions {
+                recompute_normals: true,
+                ..NONE
+            },
+        );
+        assert_eq!(json.vertex_normals, mesh().vertex_normals);
+    }
+
+    #[test]
+    fn disabled_repairs_change_nothing() {
+        let mut json = mesh();
+        json.vertex_normals[0] = f32::NAN;
+        json.vertex_frontcolors.pop();
+        let before = json.clone();
+        let report = json.validate();
+        json.repair(&report, NONE);
+        let bits = |values: &[f32]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&json.vertex_normals), bits(&before.vertex_normals));
+        assert_eq!(json.vertex_frontcolors, before.vertex_frontcolors);
+    }
+}
@@ -0,0 +1,203 @@
+use egui::{Rect, Vec2 as EguiVec2};
+use glam::{vec3, Vec2};
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
+
+use super::{
+    camera::{self, CameraPose, Projection, ProjectionKind},
+    RenderingMode,
+};
+
+/// How the central panel is split into panes.
+#[derive(
+    Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize, Default, IntoStaticStr, EnumIter,
+)]
+pub(super) enum Layout {
+    #[default]
+    Single,
+    Double,
+    Quad,
+}
+
+impl Layout {
+    pub(super) fn pane_count(self) -> usize {
+        match self {
+            Self::Single => 1,
+            Self::Double => 2,
+            Self::Quad => 4,
+        }
+    }
+
+    /// The rectangles of the visible panes, in the order of `DemoApp::panes`.
+    pub(super) fn split(self, rect: Rect) -> Vec<Rect> {
+        let half = rect.size() / 2.;
+        match self {
+            Self::Single => vec![rect],
+            Self::Double => vec![
+                Rect::from_min_size(rect.min, EguiVec2::new(half.x, rect.height())),
+                Rect::from_min_size(
+                    rect.min + EguiVec2::new(half.x, 0.),
+                    EguiVec2::new(half.x, rect.height()),
+                ),
+            ],
+            Self::Quad => [(0., 0.), (1., 0.), (0., 1.), (1., 1.)]
+                .into_iter()
+                .map(|(x, y)| {
+                    Rect::from_min_size(rect.min + EguiVec2::new(x * half.x, y * half.y), half)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Cameras a pane can be reset to, the classic quad view.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, IntoStaticStr, EnumIter)]
+pub(super) enum ViewPreset {
+    Top,
+    Front,
+    Side,
+    Perspective,
+}
+
+/// How far the orthographic presets sit from the origin.
+const PRESET_DISTANCE: f32 = 50.;
+
+impl ViewPreset {
+    fn pose(self) -> CameraPose {
+        let (pos, phi, up) = match self {
+            Self::Top => (vec3(0., PRESET_DISTANCE, 0.), 0., -90.),
+            Self::Front => (vec3(0., 0., PRESET_DISTANCE), 0., 0.),
+            Self::Side => (vec3(PRESET_DISTANCE, 0., 0.), -90., 0.),
+            Self::Perspective => (vec3(0., 0., 25.), 0., 0.),
+        };
+        CameraPose {
+            pos,
+            phi,
+            up,
+            fovy: 60.,
+        }
+    }
+
+    fn projection(self) -> Projection {
+        if self == Self::Perspective {
+            return Default::default();
+        }
+        Projection {
+            kind: ProjectionKind::Orthographic,
+            ortho_size: 30.,
+            near: 0.1,
+            far: PRESET_DISTANCE * 2.,
+            ..Default::default()
+        }
+    }
+}
+
+/// One of the viewports the central panel can be split into.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Pane {
+    pub(super) name: String,
+    /// Show what the main camera sees instead of using `pose` and `projection`.
+    pub(super) main_camera: bool,
+    pub(super) pose: CameraPose,
+    pub(super) projection: Projection,
+    /// Draw every object with this mode instead of its own.
+    pub(super) shading: Option<RenderingMode>,
+    /// Draw the main camera's frustum and ghost what it can't see.
+    pub(super) observer: bool,
+}
+
+impl Default for Pane {
+    fn default() -> Self {
+        Self::from_preset(ViewPreset::Perspective)
+    }
+}
+
+impl Pane {
+    fn from_preset(preset: ViewPreset) -> Self {
+        Self {
+            name: Into::<&'static str>::into(preset).into(),
+            main_camera: false,
+            pose: preset.pose(),
+            projection: preset.projection(),
+            shading: None,
+            observer: false,
+        }
+    }
+
+    /// The main camera followed by top, front and side views.
+    pub(super) fn default_panes() -> Vec<Self> {
+        let mut panes: Vec<_> = [
+            ViewPreset::Perspective,
+            ViewPreset::Top,
+            ViewPreset::Front,
+            ViewPreset::Side,
+        ]
+        .into_iter()
+        .map(Self::from_preset)
+        .collect();
+        panes[0].name = "Main".into();
+        panes[0].main_camera = true;
+        panes
+    }
+
+    pub(super) fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.main_camera, "Use the main camera");
+        if !self.main_camera {
+            ui.horizontal(|ui| {
+                for preset in ViewPreset::iter() {
+                    if ui.button(Into::<&'static str>::into(preset)).clicked() {
+                        self.pose = preset.pose();
+                        self.projection = preset.projection();
+                    }
+                }
+            });
+            self.projection.ui(ui, &mut self.pose.fovy);
+            ui.label("Drag to pan, scroll to zoom, right drag to look around.");
+            ui.checkbox(&mut self.observer, "Observe the main camera's frustum");
+        }
+        egui::ComboBox::new("pane_shading", "Shading")
+            .selected_text(
+                self.shading
+                    .map_or("Per object", Into::<&'static str>::into),
+            )
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.shading, None, "Per object");
+                for mode in RenderingMode::iter() {
+                    ui.selectable_value(
+                        &mut self.shading,
+                        Some(mode),
+                        Into::<&'static str>::into(mode),
+                    );
+                }
+            });
+    }
+
+    /// Mouse controls of a pane with its own camera.
+    pub(super) fn controls(&mut self, ui: &egui::Ui, rect: Rect, response: &egui::Response) {
+        let delta = Vec2::from(<[f32; 2]>::from(response.drag_delta()));
+        let pose = &mut self.pose;
+        if response.dragged_by(egui::PointerButton::Secondary) {
+            camera::look(&mut pose.phi, &mut pose.up, delta);
+        } else if response.dragged() {
+            let units_per_pixel =
+                self.projection
+                    .units_per_pixel(pose.fovy, PRESET_DISTANCE / 2., rect.height());
+            let (right, up) = camera::basis(pose.phi, pose.up);
+            pose.pos += (-right * delta.x + up * delta.y) * units_per_pixel;
+        }
+        if response.hovered() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0. {
+                match self.projection.kind {
+                    ProjectionKind::Orthographic => {
+                        self.projection.ortho_size *= (scroll * -0.002).exp()
+                    }
+                    ProjectionKind::Perspective => {
+                        pose.pos += camera::forward(pose.phi, pose.up) * scroll * 0.02
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+uniform vec3 uColor;
+uniform bool hatch;
+
+in vec3 fragPosition;
+out vec4 outputColor;
+
+// Marks the inside of the clipped models in the stencil buffer and then
+// fills it in, with diagonal stripes if asked to.
+void main(void) {
+    if(clipped(fragPosition)) {
+        discard;
+    }
+    vec3 color = uColor;
+    if(hatch && mod(gl_FragCoord.x + gl_FragCoord.y, 8.0) < 2.5) {
+        color *= 0.4;
+    }
+    outputColor = vec4(color, 1.0);
+}
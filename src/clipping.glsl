@@ -0,0 +1,16 @@
+precision highp float;
+precision highp int;
+
+// Each plane is (normal, offset), a point is kept when it is in front of
+// every one of them. MAX_CLIPPING_PLANES is defined by the app.
+uniform vec4 clipping_planes[MAX_CLIPPING_PLANES];
+uniform int clipping_plane_count;
+
+bool clipped(vec3 position) {
+    for(int i = 0; i < MAX_CLIPPING_PLANES; i++) {
+        if(i < clipping_plane_count && dot(clipping_planes[i].xyz, position) + clipping_planes[i].w < 0.) {
+            return true;
+        }
+    }
+    return false;
+}
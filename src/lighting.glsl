@@ -0,0 +1,86 @@
+precision highp float;
+precision highp int;
+
+// Shared by vertex.glsl and fragment.glsl, MAX_LIGHTS is defined by the app.
+struct Light {
+    vec4 location; // w is the type: 0 directional, 1 point, 2 spot
+    vec4 color;
+    vec4 direction;
+    vec4 attenuation; // constant, linear, quadratic
+    vec4 cone; // cosine of the inner and outer angle
+    vec4 shadow; // shadow map slot (-1 for none), bias and texel size
+    mat4 shadowMatrix; // world to shadow map clip space
+};
+
+layout(std140) uniform Lights {
+    Light lights[MAX_LIGHTS];
+    int lightCount;
+};
+
+// Direction towards light i, along with how much of it reaches mvVertex.
+vec3 lightDirection(int i, vec3 mvVertex, out float strength) {
+    int type = int(lights[i].location.w);
+    if(type == 0) {
+        strength = 1.;
+        return -lights[i].direction.xyz;
+    }
+
+    vec3 toLight = lights[i].location.xyz - mvVertex;
+    float d = length(toLight);
+    vec3 att = lights[i].attenuation.xyz;
+    strength = 1. / max(att.x + att.y * d + att.z * d * d, 1e-4);
+    if(type == 2) {
+        float cosTheta = dot(-toLight / d, lights[i].direction.xyz);
+        vec2 cone = lights[i].cone.xy;
+        strength *= smoothstep(cone.y, max(cone.x, cone.y + 1e-4), cosTheta);
+    }
+    return toLight;
+}
+
+#ifdef SHADOWS
+// How much of light i reaches mvVertex past the shadow casters, L points
+// towards the light. Defined in shadow.glsl, only the fragment stage has it.
+float shadowFactor(int i, vec3 mvVertex, vec3 L);
+#endif
+
+uniform vec3 ambient_color;
+
+// Set for every object: ka, kd, ks and the cosine exponent.
+uniform vec4 material;
+uniform vec3 materialColor;
+uniform bool useVertexColor;
+
+vec3 baseColor(vec3 vertexColor) {
+    return useVertexColor ? vertexColor : materialColor;
+}
+
+vec3 shading(vec3 mvVertex, vec3 mvNormal, vec3 color) {
+    float ka = material[0], kd = material[1], ks = material[2], CosineDegree = material[3];
+    vec3 V = -normalize(mvVertex);
+    vec3 N = normalize(mvNormal);
+    vec3 ambient = ka * ambient_color;
+
+    vec3 phong = color * ambient;
+    for(int i = 0; i < lightCount; ++i) {
+
+        float strength;
+        vec3 L = normalize(lightDirection(i, mvVertex, strength));
+        vec3 H = normalize(L + V);
+        vec3 lightColor = strength * lights[i].color.rgb;
+#ifdef SHADOWS
+        lightColor *= shadowFactor(i, mvVertex, L);
+#endif
+
+        vec3 Id = lightColor * max(dot(L, N), 0.);
+        vec3 diffuse = kd * Id;
+
+        vec3 Is = lightColor * pow(max(dot(H, N), 0.), CosineDegree);
+        vec3 specular = ks * Is;
+
+        if(dot(L, N) < 0.) {
+            specular = vec3(0., 0., 0.);
+        }
+        phong += color * diffuse + specular;
+    }
+    return phong;
+}
@@ -0,0 +1,13 @@
+precision highp float;
+
+uniform vec3 uColor;
+
+in vec3 fragPosition;
+out vec4 outputColor;
+
+void main(void) {
+    if(clipped(fragPosition)) {
+        discard;
+    }
+    outputColor = vec4(uColor, 1.0);
+}
@@ -0,0 +1,22 @@
+in vec3 aVertexPosition;
+in vec3 aVertexNormal;
+
+uniform mat4 uMVMatrix;
+uniform mat4 uPMatrix;
+uniform vec2 uViewport;
+uniform float uWidth;
+
+out vec3 fragPosition;
+
+// Pushes every vertex uWidth pixels out along its normal, the part of this
+// hull the object doesn't cover is the outline.
+void main(void) {
+    fragPosition = (uMVMatrix * vec4(aVertexPosition, 1.0)).xyz;
+    vec3 normal = transpose(inverse(mat3(uMVMatrix))) * aVertexNormal;
+    vec4 position = uPMatrix * vec4(fragPosition, 1.0);
+    vec2 offset = (uPMatrix * vec4(normal, 0.0)).xy * uViewport;
+    if(length(offset) > 0.0) {
+        position.xy += normalize(offset) * uWidth * 2.0 / uViewport * position.w;
+    }
+    gl_Position = position;
+}
@@ -0,0 +1,56 @@
+// Fragment stage only, MAX_SHADOW_MAPS, SHADOW_NEAR and SHADOW_FAR are defined
+// by the app. Spot and directional lights use shadowMaps, point lights use
+// shadowCubes, lights[i].shadow.x says which one. The samplers and
+// sampleMap/sampleCube come from shadow::shader_source.
+
+// 3x3 PCF over the 2D shadow map of light i.
+float mapShadow(int i, int slot, vec3 position) {
+    vec4 p = lights[i].shadowMatrix * vec4(position, 1.);
+    if(p.w <= 0.) {
+        return 1.;
+    }
+    vec3 coord = p.xyz / p.w * 0.5 + 0.5;
+    if(any(lessThan(coord, vec3(0.))) || any(greaterThan(coord, vec3(1.)))) {
+        return 1.;
+    }
+
+    float texel = lights[i].shadow.z;
+    float lit = 0.;
+    for(int x = -1; x <= 1; ++x) {
+        for(int y = -1; y <= 1; ++y) {
+            lit += sampleMap(slot, coord + vec3(vec2(x, y) * texel, 0.));
+        }
+    }
+    return lit / 9.;
+}
+
+// PCF over the cube map of light i, with the center and eight corner samples.
+float cubeShadow(int i, int slot, vec3 position) {
+    vec3 d = position - lights[i].location.xyz;
+    vec3 a = abs(d);
+    float major = max(a.x, max(a.y, a.z));
+    // Window depth of the face that major points into.
+    float n = SHADOW_NEAR, f = SHADOW_FAR;
+    float depth = ((f + n) / (f - n) - 2. * f * n / ((f - n) * major)) * 0.5 + 0.5;
+
+    float radius = 2. * major * lights[i].shadow.z;
+    float lit = sampleCube(slot, vec4(d, depth));
+    for(int corner = 0; corner < 8; ++corner) {
+        vec3 offset = vec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1) * 2. - 1.;
+        lit += sampleCube(slot, vec4(d + offset * radius, depth));
+    }
+    return lit / 9.;
+}
+
+float shadowFactor(int i, vec3 mvVertex, vec3 L) {
+    int slot = int(lights[i].shadow.x);
+    if(slot < 0) {
+        return 1.;
+    }
+    // The bias moves the lookup towards the light, against shadow acne.
+    vec3 position = mvVertex + L * lights[i].shadow.y;
+    if(int(lights[i].location.w) == 1) {
+        return cubeShadow(i, slot, position);
+    }
+    return mapShadow(i, slot, position);
+}
@@ -0,0 +1,10 @@
+precision highp float;
+
+in vec3 fragPosition;
+
+// Depth only, clipped away geometry doesn't cast shadows either.
+void main(void) {
+    if(clipped(fragPosition)) {
+        discard;
+    }
+}
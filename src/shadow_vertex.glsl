@@ -0,0 +1,11 @@
+in vec3 aVertexPosition;
+
+uniform mat4 uMVMatrix;
+uniform mat4 uLightMatrix;
+
+out vec3 fragPosition;
+
+void main(void) {
+    fragPosition = (uMVMatrix * vec4(aVertexPosition, 1.0)).xyz;
+    gl_Position = uLightMatrix * vec4(fragPosition, 1.0);
+}
@@ -2,21 +2,10 @@ in vec3 aVertexPosition;
 in vec3 aFrontColor;
 in vec3 aVertexNormal;
 
-uniform vec3 lightLoc[3];
-uniform vec3 lightColor[3];
-uniform vec3 lightKdKsCD[3];
 uniform mat4 uMVMatrix;
 uniform mat4 uPMatrix;
-uniform float Ka;
-uniform vec3 ambient_color;
 
 uniform int mode;
-uniform vec3 clipping_plane;
-uniform vec3 clipping_plane_pos;
-
-out vec3 shading_mode;
-out vec3 clipping_plane_f;
-out vec3 clipping_plane_pos_f;
 
 // for flat shading
 flat out vec4 flatcolor;
@@ -28,71 +17,27 @@ out vec4 fragcolor;
 out vec3 vertexColor;
 out vec3 fragPosition;
 out vec3 fragNormal;
-out vec3 lightLocations[3];
-out vec3 lightColors[3];
-out vec3 lightKdKsCDs[3];
-out float Ka_val;
-out vec3 ambient_lightColor;
-
-vec3 shading(vec3 vertex) {
-    vec3 phong = vec3(0., 0., 0.);
-    vec3 mvVertex = (uMVMatrix * vec4(vertex, 1.0)).xyz;
-    vec3 mvNormal = mat3(uMVMatrix) * aVertexNormal;
-
-    float ka = Ka;
-    vec3 V = -normalize(mvVertex);
-    vec3 N = normalize(mvNormal);
-    vec3 ambient = ka * ambient_color;
-
-    for(int i = 0; i < 3; ++i) {
-        float kd = lightKdKsCD[i][0], ks = lightKdKsCD[i][1], CosineDegree = lightKdKsCD[i][2];
-
-        vec3 L = normalize(lightLoc[i] - mvVertex);
-        vec3 H = normalize(L + V);
-
-        vec3 Id = lightColor[i] * max(dot(L, N), 0.);
-        vec3 diffuse = kd * Id;
-
-        vec3 Is = lightColor[i] * pow(max(dot(H, N), 0.), CosineDegree);
-        vec3 specular = ks * Is;
-
-        if(dot(L, N) < 0.) {
-            specular = vec3(0., 0., 0.);
-        }
-        phong += aFrontColor * (ambient + diffuse) + specular;
-    }
-    return phong;
-}
 
 void main(void) {
-    shading_mode = vec3(mode);
-
     vec3 vertex_copy = aVertexPosition;
     fragPosition = (uMVMatrix * vec4(vertex_copy, 1.0)).xyz;
-    clipping_plane_f = clipping_plane;
-    clipping_plane_pos_f = clipping_plane_pos;
+    vec3 mvNormal = mat3(uMVMatrix) * aVertexNormal;
+    vec3 color = baseColor(aFrontColor);
 
     if(mode == 0) {
         // flat shading
-        flatcolor = vec4(shading(vertex_copy), 1.0);
+        flatcolor = vec4(shading(fragPosition, mvNormal, color), 1.0);
     }
 
     if(mode == 1) {
         // gouraud shading
-        fragcolor = vec4(shading(vertex_copy), 1.0);
+        fragcolor = vec4(shading(fragPosition, mvNormal, color), 1.0);
     }
 
     if(mode == 2 || mode == 3 || mode == 4) {
         // phong shading
-        vertexColor = aFrontColor;
-        fragNormal = mat3(uMVMatrix) * aVertexNormal;
-        Ka_val = Ka;
-        ambient_lightColor = ambient_color;
-        for(int i = 0; i < 3; ++i) {
-            lightLocations[i] = lightLoc[i];
-            lightColors[i] = lightColor[i];
-            lightKdKsCDs[i] = lightKdKsCD[i];
-        }
+        vertexColor = color;
+        fragNormal = mvNormal;
     }
 
     gl_Position = uPMatrix * uMVMatrix * vec4(vertex_copy, 1.0);